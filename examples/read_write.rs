@@ -7,7 +7,7 @@ fn main() {
 
     db.update(|txn| -> Result<()> {
                     assert!(txn.get("hello").is_none());
-                    txn.update("hello", Data::Int(998)).unwrap();
+                    assert!(txn.update("hello", Data::Int(998)).is_none());
                     assert_eq!(&Data::Int(998), txn.get("hello").unwrap());
                     Ok(())
                 })
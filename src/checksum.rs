@@ -0,0 +1,31 @@
+// The reversed polynomial of the IEEE CRC32.
+const POLYNOMIAL: u32 = 0xEDB88320;
+
+// Return the IEEE CRC32 checksum of the bytes.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(0, crc32(b""));
+        assert_eq!(0xCBF43926, crc32(b"123456789"));
+        assert_eq!(0x414FA339,
+                   crc32(b"The quick brown fox jumps over the lazy dog"));
+    }
+}
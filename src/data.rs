@@ -1,10 +1,23 @@
+use std::cmp;
+use std::collections::{VecDeque, BTreeMap, BTreeSet};
 use error::*;
+use sorted_set::SortedSet;
 use serde_json;
 
-const CRLF: &'static str = "\r\n";
-const STRING_PREFIX: &'static str = "+";
-const INT_PREFIX: &'static str = ":";
-const JSON_PERFIX: &'static str = "?";
+const CRLF: &str = "\r\n";
+const STRING_PREFIX: &str = "+";
+const INT_PREFIX: &str = ":";
+const JSON_PERFIX: &str = "?";
+const BYTES_PREFIX: &str = "$";
+const FLOAT_PREFIX: &str = ",";
+const BOOL_PREFIX: &str = "#";
+const NULL_PREFIX: &str = "_";
+const LIST_PREFIX: &str = "*";
+const HASH_PREFIX: &str = "%";
+const SET_PREFIX: &str = "~";
+const SORTED_SET_PREFIX: &str = "^";
+const TRUE: &str = "t";
+const FALSE: &str = "f";
 
 macro_rules! serialize_template { () => ("{prefix}{value}{crlf}") }
 
@@ -14,17 +27,91 @@ pub enum Data {
     String(String),
     Int(i64),
     JSON(serde_json::Value),
+    Bytes(Vec<u8>),
+    Float(f64),
+    Bool(bool),
+    Null,
+    List(VecDeque<Data>),
+    Hash(BTreeMap<String, Data>),
+    Set(BTreeSet<String>),
+    SortedSet(SortedSet),
 }
 
 impl Data {
+    // Serialize the data into a string. Bytes are hex encoded, use
+    // `into_bytes` to keep them as they are.
     pub fn into_string(self) -> String {
         match self {
             Data::String(string) => Self::serialize_string(string),
             Data::Int(int) => Self::serialize_int(int),
             Data::JSON(json) => Self::serialize_json(json),
+            Data::Bytes(bytes) => Self::serialize_hex(&bytes),
+            Data::Float(float) => Self::serialize_float(float),
+            Data::Bool(boolean) => Self::serialize_bool(boolean),
+            Data::Null => Self::serialize_null(),
+            Data::List(list) => {
+                Self::serialize_items(LIST_PREFIX, list.into_iter().map(Data::into_string).collect())
+            }
+            Data::Hash(hash) => {
+                Self::serialize_items(HASH_PREFIX,
+                                      Self::hash_items(hash)
+                                          .map(Data::into_string)
+                                          .collect())
+            }
+            Data::Set(set) => {
+                Self::serialize_items(SET_PREFIX,
+                                      set.into_iter()
+                                          .map(|member| Data::String(member).into_string())
+                                          .collect())
+            }
+            Data::SortedSet(set) => {
+                Self::serialize_items(SORTED_SET_PREFIX,
+                                      Self::sorted_set_items(set)
+                                          .map(Data::into_string)
+                                          .collect())
+            }
         }
     }
 
+    // Serialize the data like `into_string`, except that bytes are
+    // not encoded.
+    pub fn into_bytes(self) -> Vec<u8> {
+        match self {
+            Data::Bytes(bytes) => {
+                let mut serialized = BYTES_PREFIX.as_bytes().to_vec();
+                serialized.extend_from_slice(&bytes);
+                serialized.extend_from_slice(CRLF.as_bytes());
+                serialized
+            }
+            Data::List(list) => {
+                Self::frame_items(LIST_PREFIX, list.into_iter().map(Data::into_bytes).collect())
+            }
+            Data::Hash(hash) => {
+                Self::frame_items(HASH_PREFIX,
+                                  Self::hash_items(hash).map(Data::into_bytes).collect())
+            }
+            Data::SortedSet(set) => {
+                Self::frame_items(SORTED_SET_PREFIX,
+                                  Self::sorted_set_items(set).map(Data::into_bytes).collect())
+            }
+            data => data.into_string().into_bytes(),
+        }
+    }
+
+    // Deserialize the data serialized by `into_bytes`.
+    pub fn try_from_bytes(bytes: Vec<u8>) -> Result<Data> {
+        if bytes.starts_with(BYTES_PREFIX.as_bytes()) {
+            if bytes.len() < 3 || !bytes.ends_with(CRLF.as_bytes()) {
+                return Err(Error::new(ErrorKind::InvalidSerializedString));
+            }
+            return Ok(Data::Bytes(bytes[1..bytes.len() - 2].to_vec()));
+        }
+        if is_collection(&bytes) {
+            return Self::from_items(&bytes, true);
+        }
+        Self::try_from(String::from_utf8(bytes)?)
+    }
+
     pub fn try_from(string: String) -> Result<Data> {
         if string.len() < 2 || !string.ends_with(CRLF) {
             return Err(Error::new(ErrorKind::InvalidSerializedString));
@@ -36,6 +123,16 @@ impl Data {
             Ok(Self::from_int(string)?)
         } else if string.starts_with(JSON_PERFIX) {
             Ok(Self::from_json(string)?)
+        } else if string.starts_with(BYTES_PREFIX) {
+            Ok(Self::from_hex(string)?)
+        } else if string.starts_with(FLOAT_PREFIX) {
+            Ok(Self::from_float(string)?)
+        } else if string.starts_with(BOOL_PREFIX) {
+            Ok(Self::from_bool(string)?)
+        } else if string.starts_with(NULL_PREFIX) {
+            Ok(Self::from_null(string)?)
+        } else if is_collection(string.as_bytes()) {
+            Ok(Self::from_items(string.as_bytes(), false)?)
         } else {
             Err(Error::new(ErrorKind::InvalidSerializedString))
         }
@@ -53,6 +150,106 @@ impl Data {
         Ok(Data::JSON(serde_json::from_str(&s[1..s.len() - 2])?))
     }
 
+    fn from_hex(s: String) -> Result<Data> {
+        let hex = &s.as_bytes()[1..s.len() - 2];
+        if !hex.len().is_multiple_of(2) {
+            return Err(Error::new(ErrorKind::InvalidSerializedString));
+        }
+        let mut bytes = Vec::with_capacity(hex.len() / 2);
+        for pair in hex.chunks(2) {
+            match (hex_value(pair[0]), hex_value(pair[1])) {
+                (Some(high), Some(low)) => bytes.push(high << 4 | low),
+                _ => return Err(Error::new(ErrorKind::InvalidSerializedString)),
+            }
+        }
+        Ok(Data::Bytes(bytes))
+    }
+
+    fn from_float(s: String) -> Result<Data> {
+        match s[1..s.len() - 2].parse::<f64>() {
+            Ok(float) => Ok(Data::Float(float)),
+            Err(_) => Err(Error::new(ErrorKind::InvalidSerializedString)),
+        }
+    }
+
+    fn from_bool(s: String) -> Result<Data> {
+        match &s[1..s.len() - 2] {
+            TRUE => Ok(Data::Bool(true)),
+            FALSE => Ok(Data::Bool(false)),
+            _ => Err(Error::new(ErrorKind::InvalidSerializedString)),
+        }
+    }
+
+    fn from_null(s: String) -> Result<Data> {
+        if s.len() != NULL_PREFIX.len() + CRLF.len() {
+            return Err(Error::new(ErrorKind::InvalidSerializedString));
+        }
+        Ok(Data::Null)
+    }
+
+    // Deserialize a collection, whose items are serialized by
+    // `into_bytes` if they are raw, otherwise by `into_string`.
+    fn from_items(bytes: &[u8], raw: bool) -> Result<Data> {
+        let items = parse_items(bytes, raw)?;
+        let prefix = &bytes[..1];
+        if prefix == LIST_PREFIX.as_bytes() {
+            return Ok(Data::List(items.into_iter().collect()));
+        }
+        if prefix == SET_PREFIX.as_bytes() {
+            let mut set = BTreeSet::new();
+            for member in items {
+                match member {
+                    Data::String(member) => set.insert(member),
+                    _ => return Err(Error::new(ErrorKind::InvalidSerializedString)),
+                };
+            }
+            return Ok(Data::Set(set));
+        }
+
+        // The items of a hash are its fields followed by their values,
+        // and those of a sorted set are its members followed by their
+        // scores.
+        if items.len() % 2 != 0 {
+            return Err(Error::new(ErrorKind::InvalidSerializedString));
+        }
+        let mut hash = BTreeMap::new();
+        let mut set = SortedSet::new();
+        let mut items = items.into_iter();
+        while let (Some(key), Some(value)) = (items.next(), items.next()) {
+            match (key, value) {
+                (Data::String(member), Data::Float(score)) if prefix ==
+                                                               SORTED_SET_PREFIX.as_bytes() &&
+                                                               !score.is_nan() => {
+                    set.insert(member, score);
+                }
+                (Data::String(field), value) if prefix == HASH_PREFIX.as_bytes() => {
+                    hash.insert(field, value);
+                }
+                _ => return Err(Error::new(ErrorKind::InvalidSerializedString)),
+            }
+        }
+        if prefix == HASH_PREFIX.as_bytes() {
+            Ok(Data::Hash(hash))
+        } else {
+            Ok(Data::SortedSet(set))
+        }
+    }
+
+    // Flatten the sorted set into its members, as strings, and their
+    // scores.
+    fn sorted_set_items(set: SortedSet) -> impl Iterator<Item = Data> {
+        set.iter()
+            .flat_map(|(member, score)| vec![Data::String(member.clone()), Data::Float(score)])
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    // Flatten the hash into its fields, as strings, and their values.
+    fn hash_items(hash: BTreeMap<String, Data>) -> impl Iterator<Item = Data> {
+        hash.into_iter()
+            .flat_map(|(field, value)| vec![Data::String(field), value])
+    }
+
     fn serialize_string(s: String) -> String {
         format!(serialize_template!(),
                 prefix = STRING_PREFIX,
@@ -73,6 +270,101 @@ impl Data {
                 value = json.to_string(),
                 crlf = CRLF)
     }
+
+    fn serialize_hex(bytes: &[u8]) -> String {
+        let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+        format!(serialize_template!(),
+                prefix = BYTES_PREFIX,
+                value = hex,
+                crlf = CRLF)
+    }
+
+    fn serialize_float(f: f64) -> String {
+        format!(serialize_template!(),
+                prefix = FLOAT_PREFIX,
+                value = f,
+                crlf = CRLF)
+    }
+
+    fn serialize_bool(b: bool) -> String {
+        format!(serialize_template!(),
+                prefix = BOOL_PREFIX,
+                value = if b { TRUE } else { FALSE },
+                crlf = CRLF)
+    }
+
+    fn serialize_null() -> String {
+        format!("{}{}", NULL_PREFIX, CRLF)
+    }
+
+    // Serialize the items of a collection, which are the number of
+    // the items, then the length of each serialized item followed by
+    // the item itself.
+    fn serialize_items(prefix: &str, items: Vec<String>) -> String {
+        let mut serialized = format!("{}{}{}", prefix, items.len(), CRLF);
+        for item in items {
+            serialized.push_str(&format!("{}{}{}", item.len(), CRLF, item));
+        }
+        serialized
+    }
+
+    // Like `serialize_items`, for the items serialized by `into_bytes`.
+    fn frame_items(prefix: &str, items: Vec<Vec<u8>>) -> Vec<u8> {
+        let mut serialized = format!("{}{}{}", prefix, items.len(), CRLF).into_bytes();
+        for item in items {
+            serialized.extend(format!("{}{}", item.len(), CRLF).into_bytes());
+            serialized.extend(item);
+        }
+        serialized
+    }
+}
+
+// Return whether the serialized data is a collection.
+fn is_collection(bytes: &[u8]) -> bool {
+    [LIST_PREFIX, HASH_PREFIX, SET_PREFIX, SORTED_SET_PREFIX]
+        .iter()
+        .any(|prefix| bytes.starts_with(prefix.as_bytes()))
+}
+
+// Parse the items of a serialized collection, which starts with its
+// one character prefix.
+fn parse_items(bytes: &[u8], raw: bool) -> Result<Vec<Data>> {
+    let (count, mut rest) = parse_len(&bytes[1..])?;
+    // The count could be damaged, so do not trust it to allocate.
+    let mut items = Vec::with_capacity(cmp::min(count, rest.len()));
+    for _ in 0..count {
+        let (len, next) = parse_len(rest)?;
+        if next.len() < len {
+            return Err(Error::new(ErrorKind::InvalidSerializedString));
+        }
+        let item = next[..len].to_vec();
+        items.push(if raw {
+                       Data::try_from_bytes(item)?
+                   } else {
+                       Data::try_from(String::from_utf8(item)?)?
+                   });
+        rest = &next[len..];
+    }
+    if !rest.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidSerializedString));
+    }
+    Ok(items)
+}
+
+// Parse a length ended by CRLF, return it and the bytes after it.
+fn parse_len(bytes: &[u8]) -> Result<(usize, &[u8])> {
+    match bytes.windows(2).position(|window| window == CRLF.as_bytes()) {
+        Some(end) => {
+            let len = String::from_utf8(bytes[..end].to_vec())?.parse::<usize>()?;
+            Ok((len, &bytes[end + 2..]))
+        }
+        None => Err(Error::new(ErrorKind::InvalidSerializedString)),
+    }
+}
+
+// Return the value of a hex digit.
+fn hex_value(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|value| value as u8)
 }
 
 #[cfg(test)]
@@ -143,4 +435,131 @@ mod tests {
         assert_eq!(Data::JSON(json!({"name": "David","age": 18})),
                    Data::try_from(String::from("?{\"age\":18,\"name\":\"David\"}\r\n")).unwrap());
     }
+
+    #[test]
+    fn test_try_from_bytes() {
+        let data = Data::Bytes(vec![0, 0xff, b'\r', b'\n', 0x80]);
+        assert_eq!("$00ff0d0a80\r\n", data.clone().into_string());
+        assert_eq!(data,
+                   Data::try_from(String::from("$00ff0d0a80\r\n")).unwrap());
+        assert_eq!(data,
+                   Data::try_from(String::from("$00FF0D0A80\r\n")).unwrap());
+        assert_eq!(data, Data::try_from_bytes(data.clone().into_bytes()).unwrap());
+        assert_eq!(b"$\x00\xff\r\n\x80\r\n".to_vec(), data.into_bytes());
+        assert_eq!(Data::Bytes(Vec::new()),
+                   Data::try_from_bytes(b"$\r\n".to_vec()).unwrap());
+        assert!(Data::try_from(String::from("$0\r\n")).is_err());
+        assert!(Data::try_from(String::from("$0g\r\n")).is_err());
+        assert!(Data::try_from_bytes(b"$\xff".to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_try_from_float() {
+        for &float in &[0.1, -2.5e300, 1.0, -0.0, f64::INFINITY] {
+            let data = Data::Float(float);
+            assert_eq!(data, Data::try_from(data.clone().into_string()).unwrap());
+            assert_eq!(data, Data::try_from_bytes(data.clone().into_bytes()).unwrap());
+        }
+        assert_eq!(",0.1\r\n", Data::Float(0.1).into_string());
+        match Data::try_from(Data::Float(f64::NAN).into_string()).unwrap() {
+            Data::Float(float) => assert!(float.is_nan()),
+            data => panic!("unexpected data: {:?}", data),
+        }
+        assert!(Data::try_from(String::from(",one\r\n")).is_err());
+    }
+
+    #[test]
+    fn test_try_from_bool() {
+        assert_eq!("#t\r\n", Data::Bool(true).into_string());
+        assert_eq!("#f\r\n", Data::Bool(false).into_string());
+        assert_eq!(Data::Bool(true),
+                   Data::try_from(String::from("#t\r\n")).unwrap());
+        assert_eq!(Data::Bool(false),
+                   Data::try_from_bytes(Data::Bool(false).into_bytes()).unwrap());
+        assert!(Data::try_from(String::from("#true\r\n")).is_err());
+    }
+
+    #[test]
+    fn test_try_from_null() {
+        assert_eq!("_\r\n", Data::Null.into_string());
+        assert_eq!(Data::Null, Data::try_from(String::from("_\r\n")).unwrap());
+        assert_eq!(Data::Null,
+                   Data::try_from_bytes(Data::Null.into_bytes()).unwrap());
+        assert!(Data::try_from(String::from("_null\r\n")).is_err());
+    }
+
+    #[test]
+    fn test_try_from_bytes_text() {
+        let data = Data::String(String::from("值"));
+        assert_eq!(data, Data::try_from_bytes(data.clone().into_bytes()).unwrap());
+        assert!(Data::try_from_bytes(vec![b'+', 0xff, b'\r', b'\n']).is_err());
+    }
+
+    #[test]
+    fn test_try_from_list() {
+        let list: VecDeque<Data> = vec![Data::String(String::from("a\r\nb")),
+                                        Data::Bytes(vec![b'\r', b'\n']),
+                                        Data::List(VecDeque::new()),
+                                        Data::Int(1)]
+                .into_iter()
+                .collect();
+        let data = Data::List(list);
+        assert_eq!("*4\r\n7\r\n+a\r\nb\r\n7\r\n$0d0a\r\n4\r\n*0\r\n4\r\n:1\r\n",
+                   data.clone().into_string());
+        assert_eq!(data, Data::try_from(data.clone().into_string()).unwrap());
+        assert_eq!(data, Data::try_from_bytes(data.clone().into_bytes()).unwrap());
+        assert_eq!(b"*1\r\n5\r\n$\r\n\r\n".to_vec(),
+                   Data::List(vec![Data::Bytes(b"\r\n".to_vec())].into_iter().collect())
+                       .into_bytes());
+
+        assert!(Data::try_from(String::from("*1\r\n")).is_err());
+        assert!(Data::try_from(String::from("*1\r\n9\r\n:1\r\n")).is_err());
+        assert!(Data::try_from(String::from("*0\r\n4\r\n:1\r\n")).is_err());
+        assert!(Data::try_from(String::from("*x\r\n")).is_err());
+        assert!(Data::try_from_bytes(b"*99999999999\r\n".to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_try_from_hash() {
+        let mut hash = BTreeMap::new();
+        hash.insert(String::from("b"), Data::Bytes(vec![b'\r', b'\n']));
+        hash.insert(String::from("a"), Data::Int(1));
+        let data = Data::Hash(hash);
+        assert_eq!("%4\r\n4\r\n+a\r\n4\r\n:1\r\n4\r\n+b\r\n7\r\n$0d0a\r\n",
+                   data.clone().into_string());
+        assert_eq!(data, Data::try_from(data.clone().into_string()).unwrap());
+        assert_eq!(data, Data::try_from_bytes(data.clone().into_bytes()).unwrap());
+        assert_eq!(Data::Hash(BTreeMap::new()),
+                   Data::try_from(String::from("%0\r\n")).unwrap());
+
+        assert!(Data::try_from(String::from("%1\r\n4\r\n+a\r\n")).is_err());
+        assert!(Data::try_from(String::from("%2\r\n4\r\n:1\r\n4\r\n:1\r\n")).is_err());
+    }
+
+    #[test]
+    fn test_try_from_set() {
+        let set: BTreeSet<String> = vec![String::from("b"), String::from("a\r\n")]
+            .into_iter()
+            .collect();
+        let data = Data::Set(set);
+        assert_eq!("~2\r\n6\r\n+a\r\n\r\n4\r\n+b\r\n", data.clone().into_string());
+        assert_eq!(data, Data::try_from(data.clone().into_string()).unwrap());
+        assert_eq!(data, Data::try_from_bytes(data.clone().into_bytes()).unwrap());
+        assert!(Data::try_from(String::from("~1\r\n4\r\n:1\r\n")).is_err());
+    }
+
+    #[test]
+    fn test_try_from_sorted_set() {
+        let mut set = SortedSet::new();
+        set.insert(String::from("b"), 1.5);
+        set.insert(String::from("a"), 2.0);
+        let data = Data::SortedSet(set);
+        assert_eq!("^4\r\n4\r\n+b\r\n6\r\n,1.5\r\n4\r\n+a\r\n4\r\n,2\r\n",
+                   data.clone().into_string());
+        assert_eq!(data, Data::try_from(data.clone().into_string()).unwrap());
+        assert_eq!(data, Data::try_from_bytes(data.clone().into_bytes()).unwrap());
+        assert!(Data::try_from(String::from("^2\r\n4\r\n+a\r\n4\r\n:1\r\n")).is_err());
+        assert!(Data::try_from(String::from("^1\r\n4\r\n+a\r\n")).is_err());
+        assert!(Data::try_from(String::from("^2\r\n4\r\n+a\r\n6\r\n,NaN\r\n")).is_err());
+    }
 }
@@ -1,25 +1,93 @@
+use std::result;
 use std::sync::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{Sender, RecvTimeoutError};
 use std::default::*;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
 use transaction::*;
+use data::*;
+use iter::*;
 use error::*;
 use persist::*;
+use store::Store;
 
-// The Clementine database.
+// The Clementine database. It is `Send` and `Sync`, so it could be
+// shared across threads with an `Arc`.
 pub struct Database {
-    pub flushes: i32,
-
-    txn_mut: RwLock<Transaction>,
+    // The data committed by the last update transaction. It is replaced
+    // on every commit, and read transactions keep the one they started
+    // with, so they never wait for update transactions.
+    snapshot: Arc<RwLock<Arc<Store>>>,
+    // Update transactions are serialized by this lock.
+    txn_mut: Arc<Mutex<Transaction>>,
     sync_policy: SyncPolicy,
+    sweep_policy: SweepPolicy,
+    compact_policy: CompactPolicy,
+    checkpoint_policy: CheckpointPolicy,
+    flushes: Arc<AtomicUsize>,
+    // The last error of the background threads, which is returned by
+    // `close`.
+    worker_error: Arc<Mutex<Option<Error>>>,
+    flusher: Option<Worker>,
+    sweeper: Option<Worker>,
+    recovery_report: Option<RecoveryReport>,
+    read_only: bool,
     closed: bool,
 }
 
+// A read transaction started by `Database::begin_read`.
+pub struct ReadGuard {
+    store: Arc<Store>,
+}
+
+// An update transaction started by `Database::begin_write`.
+pub struct WriteGuard<'a> {
+    db: &'a Database,
+    // Taken once the transaction is committed or rolled back.
+    txn: Option<MutexGuard<'a, Transaction>>,
+}
+
+// A background thread which periodically runs a task, such as
+// flushing the committed transactions to the disk.
+struct Worker {
+    stop: Sender<()>,
+    handle: JoinHandle<()>,
+}
+
 // The configuration of the Database.
 pub struct Config {
     // Whether the database should persist its data on
     // dist or just in memory.
-    persist_type: PersistType,
+    pub persist_type: PersistType,
     // The sync prolicy.
-    sync_policy: SyncPolicy,
+    pub sync_policy: SyncPolicy,
+    // How to open the file when persisting on disk.
+    pub open_mode: OpenMode,
+    // How to deal with a damaged file on load.
+    pub recovery_mode: RecoveryMode,
+    // When to compact the file.
+    pub compact_policy: CompactPolicy,
+    // When to dump the data to a snapshot and start a fresh log.
+    pub checkpoint_policy: CheckpointPolicy,
+    // Whether the database is only for reading. The file must exist,
+    // and it is never modified, so it could be opened by other read
+    // only databases at the same time.
+    pub read_only: bool,
+    // How to remove the expired keys.
+    pub sweep_policy: SweepPolicy,
+}
+
+// How the expired keys are removed. They are never seen once expired,
+// but they are kept until removed.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SweepPolicy {
+    // Only remove them when they are updated or removed.
+    Never,
+    // Periodically remove them in the background.
+    Every(Duration),
 }
 
 // The default configuration of the Database.
@@ -28,6 +96,15 @@ impl Default for Config {
         Config {
             persist_type: PersistType::Memory,
             sync_policy: SyncPolicy::Never,
+            open_mode: OpenMode::OpenOrCreate,
+            recovery_mode: RecoveryMode::Strict,
+            compact_policy: CompactPolicy::Auto {
+                min_size: 64 * 1024 * 1024,
+                ratio: 2.0,
+            },
+            checkpoint_policy: CheckpointPolicy::Never,
+            read_only: false,
+            sweep_policy: SweepPolicy::Never,
         }
     }
 }
@@ -35,70 +112,586 @@ impl Default for Config {
 impl Database {
     // Return a new instance of the Database.
     pub fn new(config: Config) -> Result<Database> {
-        let mut persist_store: Box<Persistable> = match config.persist_type {
-            PersistType::Memory => Box::new(MemoryStore::default()),
-            PersistType::File(path) => Box::new(FileStore::new(path)?),
+        if let SyncPolicy::Every(interval) = config.sync_policy {
+            if interval <= Duration::from_secs(5) {
+                return Err(Error::new(ErrorKind::InvalidSyncDuration));
+            }
+        }
+
+        // The committed transactions are only queued if they are saved.
+        let save_commits = !config.read_only && config.sync_policy != SyncPolicy::Never &&
+                           !matches!(config.persist_type, PersistType::Memory);
+        let (store, persist_store, recovery_report): (_, Box<dyn Persistable>, _) =
+            match config.persist_type {
+                PersistType::Memory => {
+                    let mut persist_store = MemoryStore::default();
+                    (persist_store.load()?, Box::new(persist_store), None)
+                }
+                PersistType::File(path) => {
+                    let mut persist_store = if config.read_only {
+                        FileStore::open_read_only(path)?
+                    } else {
+                        FileStore::new(path, config.open_mode)?
+                    };
+                    let (store, report) = match config.recovery_mode {
+                        RecoveryMode::Strict => (persist_store.load()?, None),
+                        RecoveryMode::TruncateTail => persist_store.recover()?,
+                    };
+                    (store, Box::new(persist_store), report)
+                }
+            };
+
+        let txn = Transaction::new(store, persist_store, save_commits);
+        let snapshot = Arc::new(RwLock::new(txn.snapshot()));
+        let txn_mut = Arc::new(Mutex::new(txn));
+        let flushes = Arc::new(AtomicUsize::new(0));
+        let worker_error = Arc::new(Mutex::new(None));
+        let mut db = Database {
+            snapshot,
+            txn_mut,
+            sync_policy: config.sync_policy,
+            sweep_policy: config.sweep_policy,
+            compact_policy: config.compact_policy,
+            checkpoint_policy: config.checkpoint_policy,
+            flushes,
+            worker_error,
+            flusher: None,
+            sweeper: None,
+            recovery_report,
+            read_only: config.read_only,
+            closed: false,
         };
+        db.start_workers();
+        Ok(db)
+    }
 
-        Ok(Database {
-               flushes: 0,
-               txn_mut: RwLock::new(Transaction::new(persist_store.load()?, persist_store)),
-               sync_policy: config.sync_policy,
-               closed: false,
-           })
+    // Start the background threads required by the policies.
+    fn start_workers(&mut self) {
+        if self.read_only {
+            return;
+        }
+        if let SyncPolicy::Every(interval) = self.sync_policy {
+            let (txn_mut, flushes) = (self.txn_mut.clone(), self.flushes.clone());
+            let worker_error = self.worker_error.clone();
+            let (compact_policy, checkpoint_policy) = (self.compact_policy,
+                                                       self.checkpoint_policy);
+            self.flusher = Some(Worker::start(interval, move || {
+                let result = flush(&txn_mut, &flushes, compact_policy, checkpoint_policy);
+                record_error(&worker_error, result);
+            }));
+        }
+        if let SweepPolicy::Every(interval) = self.sweep_policy {
+            let (txn_mut, snapshot) = (self.txn_mut.clone(), self.snapshot.clone());
+            let worker_error = self.worker_error.clone();
+            let sync_policy = self.sync_policy;
+            self.sweeper = Some(Worker::start(interval, move || {
+                record_error(&worker_error, sweep(&txn_mut, &snapshot, sync_policy));
+            }));
+        }
+    }
+
+    // Return how many times the committed transactions have been
+    // flushed to the disk in the background.
+    pub fn flushes(&self) -> usize {
+        self.flushes.load(Ordering::SeqCst)
+    }
+
+    // Return what was dropped from the damaged file on load when
+    // opened with `RecoveryMode::TruncateTail`.
+    pub fn recovery_report(&self) -> Option<&RecoveryReport> {
+        self.recovery_report.as_ref()
     }
 
-    // Start a read transaction.
-    pub fn read<F, K>(&self, f: F) -> Result<()>
-        where F: Fn(&ReadTransaction<K>) -> Result<()>,
+    // Start a read transaction, and return the value returned
+    // by the transaction.
+    pub fn read<T, F, K>(&self, f: F) -> Result<T>
+        where F: FnOnce(&dyn ReadTransaction<K>) -> Result<T>,
               K: Into<String> + Ord + Clone
     {
-        let store = self.txn_mut.read()?;
-        if self.closed {
-            return Err(Error::new(ErrorKind::DataBaseClosed));
-        }
-        f(&*store)
+        f(&self.begin_read()?)
     }
 
     // Start an update transaction. If all operations in the
     // transaction are successful, then the result will be persisted
-    // recording to the sync policy, otherwise will just rollback.
-    pub fn update<F, K>(&self, f: F) -> Result<()>
-        where F: Fn(&mut WriteTransaction<K>) -> Result<()>,
-              K: Into<String> + Ord + Clone
+    // recording to the sync policy, otherwise will rollback and
+    // return the error of the transaction. The error type could be
+    // any type which can be converted from the clementine error. If
+    // the transaction panics, it is rolled back as well, and
+    // `ErrorKind::TransactionPanicked` is returned.
+    pub fn update<E, T, F, K>(&self, f: F) -> result::Result<T, E>
+        where F: FnOnce(&mut dyn WriteTransaction<K>) -> result::Result<T, E>,
+              K: Into<String> + Ord + Clone,
+              E: From<Error>
     {
-        let mut store = self.txn_mut.write()?;
+        let mut txn = self.begin_write()?;
+        match panic::catch_unwind(AssertUnwindSafe(|| f(&mut txn))) {
+            Ok(Ok(value)) => {
+                txn.commit()?;
+                Ok(value)
+            }
+            Ok(Err(err)) => {
+                txn.rollback();
+                Err(err)
+            }
+            Err(_) => {
+                txn.rollback();
+                Err(E::from(Error::new(ErrorKind::TransactionPanicked)))
+            }
+        }
+    }
+
+    // Start a read transaction which is held until the returned guard
+    // is dropped. It sees the data committed before it is started, and
+    // does not block or wait for update transactions.
+    pub fn begin_read(&self) -> Result<ReadGuard> {
         if self.closed {
             return Err(Error::new(ErrorKind::DataBaseClosed));
         }
+        let store = self.snapshot.read().unwrap_or_else(PoisonError::into_inner).clone();
+        Ok(ReadGuard { store })
+    }
 
-        // If transaction is failed, do the rollback, else do the
-        // sync job if specified.
-        if f(&mut *store).is_err() {
-            store.rollback();
-        } else if self.sync_policy == SyncPolicy::Always {
-            store.save()?;
+    // Start an update transaction which is held until the returned
+    // guard is committed or rolled back. It is rolled back if the
+    // guard is dropped without being committed. The other update
+    // transactions are blocked meanwhile, so starting another one in
+    // the same thread will deadlock. Read transactions are not blocked,
+    // and they do not see the changes until they are committed.
+    pub fn begin_write(&self) -> Result<WriteGuard<'_>> {
+        let txn = lock(&self.txn_mut);
+        if self.closed {
+            return Err(Error::new(ErrorKind::DataBaseClosed));
         }
+        if self.read_only {
+            return Err(Error::new(ErrorKind::TransactionNotWritable));
+        }
+        Ok(WriteGuard {
+               db: self,
+               txn: Some(txn),
+           })
+    }
 
-        store.commit();
-        Ok(())
+    // Rewrite the file with only the live data, so it does not grow
+    // without bound. Read transactions are not blocked during the
+    // compaction.
+    pub fn compact(&self) -> Result<()> {
+        let mut txn = lock(&self.txn_mut);
+        if self.closed {
+            return Err(Error::new(ErrorKind::DataBaseClosed));
+        }
+        if self.read_only {
+            return Err(Error::new(ErrorKind::TransactionNotWritable));
+        }
+        txn.compact()
     }
 
-    // Close this database.
+    // Dump the data to a snapshot and start a fresh log, so only the
+    // log written since then is replayed when the database is opened.
+    // Read transactions are not blocked during the checkpoint.
+    pub fn checkpoint(&self) -> Result<()> {
+        let mut txn = lock(&self.txn_mut);
+        if self.closed {
+            return Err(Error::new(ErrorKind::DataBaseClosed));
+        }
+        if self.read_only {
+            return Err(Error::new(ErrorKind::TransactionNotWritable));
+        }
+        txn.checkpoint()
+    }
+
+    // Close this database. The background threads are stopped and
+    // all the pending transactions are flushed to the disk. If the
+    // flush fails, the threads are started again and the database is
+    // left open, so closing it could be retried. An error of the
+    // background threads is returned as well, even though the database
+    // is closed.
     pub fn close(&mut self) -> Result<()> {
         if self.closed {
             return Err(Error::new(ErrorKind::DataBaseClosed));
         }
-        let _ = self.txn_mut.write()?;
+        if let Some(sweeper) = self.sweeper.take() {
+            sweeper.stop();
+        }
+        if let Some(flusher) = self.flusher.take() {
+            flusher.stop();
+        }
+        let mut result = Ok(());
+        if !self.read_only && self.sync_policy != SyncPolicy::Never {
+            result = flush(&self.txn_mut,
+                           &self.flushes,
+                           self.compact_policy,
+                           self.checkpoint_policy);
+        }
+        if let Err(err) = result.and_then(|_| lock(&self.txn_mut).close()) {
+            self.start_workers();
+            return Err(err);
+        }
         self.closed = true;
+        match self.worker_error.lock().unwrap_or_else(PoisonError::into_inner).take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+impl ReadGuard {
+    // End the transaction. Nothing is changed by a read transaction,
+    // so it is the same as `rollback` or dropping the guard, which are
+    // kept to match `WriteGuard`.
+    pub fn commit(self) -> Result<()> {
         Ok(())
     }
+
+    // End the transaction, like `commit`.
+    pub fn rollback(self) {}
+}
+
+impl<K> ReadTransaction<K> for ReadGuard
+    where K: Into<String> + Ord + Clone
+{
+    fn get(&self, key: K) -> Option<&Data> {
+        self.store.get(&key.into())
+    }
+
+    fn get_with_version(&self, key: K) -> Option<(&Data, u64)> {
+        self.store.get_with_version(&key.into())
+    }
+
+    fn ttl(&self, key: K) -> Option<Duration> {
+        self.store.ttl(&key.into())
+    }
+
+    fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.store.is_empty()
+    }
+
+    fn contains_key(&self, key: K) -> bool {
+        self.store.contains_key(&key.into())
+    }
+
+    fn iter(&self) -> Iter<'_> {
+        Iter::all(&self.store)
+    }
+
+    fn keys(&self) -> Keys<'_> {
+        Iter::all(&self.store).keys()
+    }
+
+    fn range(&self, range: Range<K>) -> Iter<'_> {
+        Iter::range(&self.store, range.start.into(), range.end.into())
+    }
+
+    fn scan_prefix(&self, prefix: K) -> Iter<'_> {
+        Iter::prefix(&self.store, prefix.into())
+    }
+}
+
+impl<'a> WriteGuard<'a> {
+    // Commit the transaction, which is persisted recording to the sync
+    // policy, then seen by the read transactions started after it. If
+    // it could not be saved, it is rolled back instead.
+    pub fn commit(mut self) -> Result<()> {
+        let mut txn = self.txn.take().unwrap();
+        if self.db.sync_policy != SyncPolicy::Always {
+            txn.commit();
+            publish(&txn, &self.db.snapshot);
+            return Ok(());
+        }
+
+        txn.commit_and_save()?;
+        publish(&txn, &self.db.snapshot);
+        drop(txn);
+        maintain(&self.db.txn_mut,
+                 self.db.compact_policy,
+                 self.db.checkpoint_policy)
+    }
+
+    // Undo all the changes of the transaction.
+    pub fn rollback(mut self) {
+        if let Some(mut txn) = self.txn.take() {
+            txn.rollback();
+            txn.commit();
+        }
+    }
+
+    fn txn(&self) -> &Transaction {
+        self.txn.as_ref().unwrap()
+    }
+
+    fn txn_mut(&mut self) -> &mut Transaction {
+        self.txn.as_mut().unwrap()
+    }
+}
+
+impl<'a> Drop for WriteGuard<'a> {
+    fn drop(&mut self) {
+        if let Some(mut txn) = self.txn.take() {
+            txn.rollback();
+            txn.commit();
+        }
+    }
+}
+
+impl<'a, K> ReadTransaction<K> for WriteGuard<'a>
+    where K: Into<String> + Ord + Clone
+{
+    fn get(&self, key: K) -> Option<&Data> {
+        ReadTransaction::get(self.txn(), key)
+    }
+
+    fn get_with_version(&self, key: K) -> Option<(&Data, u64)> {
+        ReadTransaction::get_with_version(self.txn(), key)
+    }
+
+    fn ttl(&self, key: K) -> Option<Duration> {
+        ReadTransaction::ttl(self.txn(), key)
+    }
+
+    fn len(&self) -> usize {
+        ReadTransaction::<K>::len(self.txn())
+    }
+
+    fn is_empty(&self) -> bool {
+        ReadTransaction::<K>::is_empty(self.txn())
+    }
+
+    fn contains_key(&self, key: K) -> bool {
+        ReadTransaction::contains_key(self.txn(), key)
+    }
+
+    fn iter(&self) -> Iter<'_> {
+        ReadTransaction::<K>::iter(self.txn())
+    }
+
+    fn keys(&self) -> Keys<'_> {
+        ReadTransaction::<K>::keys(self.txn())
+    }
+
+    fn range(&self, range: Range<K>) -> Iter<'_> {
+        ReadTransaction::range(self.txn(), range)
+    }
+
+    fn scan_prefix(&self, prefix: K) -> Iter<'_> {
+        ReadTransaction::scan_prefix(self.txn(), prefix)
+    }
+}
+
+impl<'a, K> WriteTransaction<K> for WriteGuard<'a>
+    where K: Into<String> + Ord + Clone
+{
+    fn update(&mut self, key: K, value: Data) -> Option<Data> {
+        WriteTransaction::update(self.txn_mut(), key, value)
+    }
+
+    fn update_with_ttl(&mut self, key: K, value: Data, ttl: Duration) -> Option<Data> {
+        WriteTransaction::update_with_ttl(self.txn_mut(), key, value, ttl)
+    }
+
+    fn expire(&mut self, key: K, ttl: Duration) -> bool {
+        WriteTransaction::expire(self.txn_mut(), key, ttl)
+    }
+
+    fn persist(&mut self, key: K) -> bool {
+        WriteTransaction::persist(self.txn_mut(), key)
+    }
+
+    fn remove(&mut self, key: K) -> Option<Data> {
+        WriteTransaction::remove(self.txn_mut(), key)
+    }
+
+    fn clear(&mut self) {
+        WriteTransaction::<K>::clear(self.txn_mut())
+    }
+
+    fn compare_and_swap(&mut self, key: K, expected: Expected, value: Data) -> Result<u64> {
+        WriteTransaction::compare_and_swap(self.txn_mut(), key, expected, value)
+    }
+
+    fn compare_and_remove(&mut self, key: K, expected: Expected) -> Result<Data> {
+        WriteTransaction::compare_and_remove(self.txn_mut(), key, expected)
+    }
+
+    fn lpush(&mut self, key: K, value: Data) -> Result<usize> {
+        WriteTransaction::lpush(self.txn_mut(), key, value)
+    }
+
+    fn rpush(&mut self, key: K, value: Data) -> Result<usize> {
+        WriteTransaction::rpush(self.txn_mut(), key, value)
+    }
+
+    fn lpop(&mut self, key: K) -> Result<Option<Data>> {
+        WriteTransaction::lpop(self.txn_mut(), key)
+    }
+
+    fn rpop(&mut self, key: K) -> Result<Option<Data>> {
+        WriteTransaction::rpop(self.txn_mut(), key)
+    }
+
+    fn lrem(&mut self, key: K, count: i64, value: Data) -> Result<usize> {
+        WriteTransaction::lrem(self.txn_mut(), key, count, value)
+    }
+
+    fn ltrim(&mut self, key: K, start: i64, stop: i64) -> Result<()> {
+        WriteTransaction::ltrim(self.txn_mut(), key, start, stop)
+    }
+
+    fn hset(&mut self, key: K, field: &str, value: Data) -> Result<Option<Data>> {
+        WriteTransaction::hset(self.txn_mut(), key, field, value)
+    }
+
+    fn hdel(&mut self, key: K, field: &str) -> Result<Option<Data>> {
+        WriteTransaction::hdel(self.txn_mut(), key, field)
+    }
+
+    fn hincrby(&mut self, key: K, field: &str, delta: i64) -> Result<i64> {
+        WriteTransaction::hincrby(self.txn_mut(), key, field, delta)
+    }
+
+    fn sadd(&mut self, key: K, member: &str) -> Result<bool> {
+        WriteTransaction::sadd(self.txn_mut(), key, member)
+    }
+
+    fn srem(&mut self, key: K, member: &str) -> Result<bool> {
+        WriteTransaction::srem(self.txn_mut(), key, member)
+    }
+
+    fn zadd(&mut self, key: K, member: &str, score: f64) -> Result<Option<f64>> {
+        WriteTransaction::zadd(self.txn_mut(), key, member, score)
+    }
+
+    fn zrem(&mut self, key: K, member: &str) -> Result<Option<f64>> {
+        WriteTransaction::zrem(self.txn_mut(), key, member)
+    }
+
+    fn zpopmin(&mut self, key: K) -> Result<Option<(String, f64)>> {
+        WriteTransaction::zpopmin(self.txn_mut(), key)
+    }
+
+    fn incr_by(&mut self, key: K, delta: i64) -> Result<i64> {
+        WriteTransaction::incr_by(self.txn_mut(), key, delta)
+    }
+
+    fn decr_by(&mut self, key: K, delta: i64) -> Result<i64> {
+        WriteTransaction::decr_by(self.txn_mut(), key, delta)
+    }
+
+    fn incr_by_float(&mut self, key: K, delta: f64) -> Result<f64> {
+        WriteTransaction::incr_by_float(self.txn_mut(), key, delta)
+    }
+
+    fn savepoint(&mut self) -> Savepoint {
+        WriteTransaction::<K>::savepoint(self.txn_mut())
+    }
+
+    fn rollback_to(&mut self, savepoint: &Savepoint) {
+        WriteTransaction::<K>::rollback_to(self.txn_mut(), savepoint)
+    }
+
+    fn release(&mut self, savepoint: Savepoint) {
+        WriteTransaction::<K>::release(self.txn_mut(), savepoint)
+    }
+}
+
+impl Worker {
+    fn start<F>(interval: Duration, mut task: F) -> Worker
+        where F: FnMut() + Send + 'static
+    {
+        let (stop, stopped) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                task();
+            }
+        });
+
+        Worker {
+            stop,
+            handle,
+        }
+    }
+
+    fn stop(self) {
+        let _ = self.stop.send(());
+        let _ = self.handle.join();
+    }
+}
+
+// Save the committed transactions and sync them to the disk.
+fn flush(txn_mut: &Mutex<Transaction>,
+         flushes: &AtomicUsize,
+         compact_policy: CompactPolicy,
+         checkpoint_policy: CheckpointPolicy)
+         -> Result<()> {
+    {
+        let mut txn = lock(txn_mut);
+        txn.save()?;
+        txn.sync()?;
+        flushes.fetch_add(1, Ordering::SeqCst);
+    }
+    maintain(txn_mut, compact_policy, checkpoint_policy)
+}
+
+// Remove the expired keys in an update transaction, which is
+// persisted recording to the sync policy.
+fn sweep(txn_mut: &Mutex<Transaction>,
+         snapshot: &RwLock<Arc<Store>>,
+         sync_policy: SyncPolicy)
+         -> Result<()> {
+    let mut txn = lock(txn_mut);
+    if txn.remove_expired() == 0 {
+        return Ok(());
+    }
+    if sync_policy == SyncPolicy::Always {
+        txn.commit_and_save()?;
+    } else {
+        txn.commit();
+    }
+    publish(&txn, snapshot);
+    Ok(())
+}
+
+// Let the read transactions started from now on see the data
+// committed by the transaction.
+fn publish(txn: &Transaction, snapshot: &RwLock<Arc<Store>>) {
+    *snapshot.write().unwrap_or_else(PoisonError::into_inner) = txn.snapshot();
+}
+
+// Checkpoint or compact the persisted data if it is required by the
+// policies. Readers are not blocked meanwhile.
+fn maintain(txn_mut: &Mutex<Transaction>,
+            compact_policy: CompactPolicy,
+            checkpoint_policy: CheckpointPolicy)
+            -> Result<()> {
+    let mut txn = lock(txn_mut);
+    if txn.should_checkpoint(checkpoint_policy)? {
+        txn.checkpoint()?;
+    } else if txn.should_compact(compact_policy)? {
+        txn.compact()?;
+    }
+    Ok(())
+}
+
+// Keep the error of a background thread, so it is not lost.
+fn record_error(worker_error: &Mutex<Option<Error>>, result: Result<()>) {
+    if let Err(err) = result {
+        *worker_error.lock().unwrap_or_else(PoisonError::into_inner) = Some(err);
+    }
+}
+
+// Lock the transaction. The lock is poisoned if a transaction panics,
+// but then the transaction is already rolled back when its guard is
+// dropped, so the lock is still usable.
+fn lock(txn_mut: &Mutex<Transaction>) -> MutexGuard<'_, Transaction> {
+    txn_mut.lock().unwrap_or_else(PoisonError::into_inner)
 }
 
 impl Drop for Database {
     fn drop(&mut self) {
         if !self.closed {
-            self.close().unwrap();
+            let _ = self.close();
         }
     }
 }
@@ -110,8 +703,95 @@ mod tests {
     #[test]
     fn test_new() {
         let db = Database::new(Config::default()).unwrap();
-        assert_eq!(false, db.closed);
-        assert_eq!(SyncPolicy::Never, db.sync_policy)
+        assert!(!db.closed);
+        assert_eq!(SyncPolicy::Never, db.sync_policy);
+        assert!(db.flusher.is_none());
+        assert!(db.sweeper.is_none());
+    }
+
+    #[test]
+    fn test_new_invalid_sync_duration() {
+        let config = Config {
+            sync_policy: SyncPolicy::Every(Duration::from_secs(1)),
+            ..Config::default()
+        };
+        assert!(Database::new(config).is_err());
+        let config = Config {
+            sync_policy: SyncPolicy::Every(Duration::from_secs(5)),
+            ..Config::default()
+        };
+        assert!(Database::new(config).is_err());
+    }
+
+    #[test]
+    fn test_close_flush() {
+        let mut db = Database::new(Config {
+                                       sync_policy: SyncPolicy::Every(Duration::from_secs(6)),
+                                       ..Config::default()
+                                   })
+                .unwrap();
+        assert_eq!(0, db.flushes());
+        assert!(db.close().is_ok());
+        assert_eq!(1, db.flushes());
+    }
+
+    #[test]
+    fn test_read_only() {
+        let db = Database::new(Config {
+                                   read_only: true,
+                                   sync_policy: SyncPolicy::Every(Duration::from_secs(6)),
+                                   ..Config::default()
+                               })
+                .unwrap();
+        assert!(db.flusher.is_none());
+        assert!(db.read(|txn| -> Result<bool> { Ok(txn.get("key").is_none()) })
+                    .unwrap());
+        match db.update(|txn| -> Result<()> {
+                            txn.update("key", Data::Int(1));
+                            Ok(())
+                        }) {
+            Err(Error { kind: ErrorKind::TransactionNotWritable }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert!(db.compact().is_err());
+        assert!(db.checkpoint().is_err());
+    }
+
+    #[test]
+    fn test_update_panic() {
+        let db = Database::new(Config::default()).unwrap();
+        let result = db.update(|txn| -> Result<()> {
+                                   txn.update("key", Data::Int(1));
+                                   panic!("boom");
+                               });
+        match result {
+            Err(Error { kind: ErrorKind::TransactionPanicked }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert!(!db.txn_mut.is_poisoned());
+        assert!(db.read(|txn| -> Result<bool> { Ok(txn.get("key").is_none()) })
+                    .unwrap());
+    }
+
+    #[test]
+    fn test_sweep() {
+        let db = Database::new(Config {
+                                   sweep_policy: SweepPolicy::Every(Duration::from_millis(10)),
+                                   ..Config::default()
+                               })
+                .unwrap();
+        db.update(|txn| -> Result<()> {
+                      txn.update_with_ttl("key", Data::Int(1), Duration::from_millis(1));
+                      txn.update("other", Data::Int(2));
+                      Ok(())
+                  })
+            .unwrap();
+        assert_eq!(2, db.begin_read().unwrap().store.data().len());
+
+        thread::sleep(Duration::from_millis(100));
+        let txn = db.begin_read().unwrap();
+        assert_eq!(1, txn.store.data().len());
+        assert!(txn.store.deadlines().is_empty());
     }
 
     #[test]
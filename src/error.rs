@@ -13,12 +13,20 @@ pub type Result<T> = result::Result<T, Error>;
 pub enum ErrorKind {
     // Database errors.
     DataBaseClosed,
+    DatabaseLocked,
     InvalidSyncDuration,
     // Transaction errors.
     TransactionNotWritable,
+    TransactionPanicked,
     ItemNotFound,
+    Conflict,
+    WrongType,
+    Overflow,
+    InvalidScore,
     // Data errors.
     InvalidSerializedString,
+    ChecksumMismatch,
+    UnsupportedFormatVersion,
     JsonParseError,
     // IO errors
     IOError,
@@ -32,16 +40,24 @@ pub struct Error {
 
 impl Error {
     pub fn new(kind: ErrorKind) -> Error {
-        Error { kind: kind }
+        Error { kind }
     }
 
     pub fn message(&self) -> &str {
         match self.kind {
             ErrorKind::DataBaseClosed => "database already closed",
-            ErrorKind::InvalidSyncDuration => "sync duration must > 5 second",
+            ErrorKind::DatabaseLocked => "database is locked",
+            ErrorKind::InvalidSyncDuration => "sync duration must be longer than 5 seconds",
             ErrorKind::TransactionNotWritable => "transaction is not writable",
+            ErrorKind::TransactionPanicked => "transaction panicked",
             ErrorKind::ItemNotFound => "item not found",
+            ErrorKind::Conflict => "item is not as expected",
+            ErrorKind::WrongType => "item holds the wrong type of data",
+            ErrorKind::Overflow => "integer overflow",
+            ErrorKind::InvalidScore => "score is not a number",
             ErrorKind::InvalidSerializedString => "invalid serialized string",
+            ErrorKind::ChecksumMismatch => "checksum mismatch",
+            ErrorKind::UnsupportedFormatVersion => "unsupported format version",
             ErrorKind::IOError => "io error",
             ErrorKind::RWLockPoisonError => "rwlock poison error",
             ErrorKind::JsonParseError => "json parse error",
@@ -63,7 +79,7 @@ impl error::Error for Error {
 
 impl From<ErrorKind> for Error {
     fn from(kind: ErrorKind) -> Error {
-        Error { kind: kind }
+        Error { kind }
     }
 }
 
@@ -97,9 +113,3 @@ impl From<string::FromUtf8Error> for Error {
     }
 }
 
-
-impl From<io::CharsError> for Error {
-    fn from(_: io::CharsError) -> Error {
-        Error { kind: ErrorKind::IOError }
-    }
-}
@@ -0,0 +1,179 @@
+use std::collections::Bound::{Included, Excluded, Unbounded};
+use std::char;
+use data::*;
+use map::{self, Map};
+use store::{self, Store};
+
+// An iterator over the items of a transaction, ordered by key. The
+// expired items are skipped.
+pub struct Iter<'a> {
+    inner: map::Range<'a, String, Data>,
+    deadlines: &'a Map<String, u64>,
+    now: u64,
+}
+
+// An iterator over the keys of a transaction, in order.
+pub struct Keys<'a> {
+    inner: Iter<'a>,
+}
+
+impl<'a> Iter<'a> {
+    // Iterate over all the items of the store.
+    pub(crate) fn all(store: &'a Store) -> Iter<'a> {
+        Iter::new(store, store.data().iter())
+    }
+
+    // Iterate over the items whose key is in [start, end).
+    pub(crate) fn range(store: &'a Store, start: String, end: String) -> Iter<'a> {
+        Iter::new(store, store.data().range(Included(start), Excluded(end)))
+    }
+
+    // Iterate over the items whose key starts with the prefix.
+    pub(crate) fn prefix(store: &'a Store, prefix: String) -> Iter<'a> {
+        let inner = match prefix_upper_bound(&prefix) {
+            Some(end) => store.data().range(Included(prefix), Excluded(end)),
+            None => store.data().range(Included(prefix), Unbounded),
+        };
+        Iter::new(store, inner)
+    }
+
+    fn new(store: &'a Store, inner: map::Range<'a, String, Data>) -> Iter<'a> {
+        let deadlines = store.deadlines();
+        // The time is only needed if any item could expire.
+        let now = if deadlines.is_empty() { 0 } else { store::now() };
+        Iter {
+            inner,
+            deadlines,
+            now,
+        }
+    }
+
+    fn is_expired(&self, key: &str) -> bool {
+        match self.deadlines.get(key) {
+            Some(&deadline) => deadline <= self.now,
+            None => false,
+        }
+    }
+
+    // Iterate over the keys only.
+    pub(crate) fn keys(self) -> Keys<'a> {
+        Keys { inner: self }
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a String, &'a Data);
+
+    fn next(&mut self) -> Option<(&'a String, &'a Data)> {
+        while let Some((key, value)) = self.inner.next() {
+            if !self.is_expired(key) {
+                return Some((key, value));
+            }
+        }
+        None
+    }
+}
+
+impl<'a> DoubleEndedIterator for Iter<'a> {
+    fn next_back(&mut self) -> Option<(&'a String, &'a Data)> {
+        while let Some((key, value)) = self.inner.next_back() {
+            if !self.is_expired(key) {
+                return Some((key, value));
+            }
+        }
+        None
+    }
+}
+
+impl<'a> Iterator for Keys<'a> {
+    type Item = &'a String;
+
+    fn next(&mut self) -> Option<&'a String> {
+        self.inner.next().map(|(key, _)| key)
+    }
+}
+
+impl<'a> DoubleEndedIterator for Keys<'a> {
+    fn next_back(&mut self) -> Option<&'a String> {
+        self.inner.next_back().map(|(key, _)| key)
+    }
+}
+
+// Return the smallest string which is greater than all the strings
+// starting with the prefix, or None if there is no such string.
+fn prefix_upper_bound(prefix: &str) -> Option<String> {
+    let mut chars: Vec<char> = prefix.chars().collect();
+    while let Some(ch) = chars.pop() {
+        let next = match ch as u32 + 1 {
+            // Skip the surrogate code points.
+            0xD800 => Some('\u{E000}'),
+            code => char::from_u32(code),
+        };
+        if let Some(next) = next {
+            chars.push(next);
+            return Some(chars.into_iter().collect());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_prefix_upper_bound() {
+        assert_eq!(Some(String::from("user;")), prefix_upper_bound("user:"));
+        assert_eq!(Some(String::from("b")), prefix_upper_bound("a\u{10FFFF}"));
+        assert_eq!(Some(String::from("\u{E000}")), prefix_upper_bound("\u{D7FF}"));
+        assert_eq!(None, prefix_upper_bound("\u{10FFFF}"));
+        assert_eq!(None, prefix_upper_bound(""));
+    }
+
+    #[test]
+    fn test_prefix() {
+        let mut store = BTreeMap::new();
+        store.insert(String::from("user"), Data::Int(0));
+        store.insert(String::from("user:1"), Data::Int(1));
+        store.insert(String::from("user:2"), Data::Int(2));
+        store.insert(String::from("user;"), Data::Int(3));
+        let store = Store::new(store);
+
+        let keys: Vec<&String> = Iter::prefix(&store, String::from("user:")).keys().collect();
+        assert_eq!(vec!["user:1", "user:2"], keys);
+        let keys: Vec<&String> = Iter::prefix(&store, String::from("")).keys().rev().collect();
+        assert_eq!(vec!["user;", "user:2", "user:1", "user"], keys);
+    }
+
+    #[test]
+    fn test_range() {
+        let mut store = BTreeMap::new();
+        store.insert(String::from("a"), Data::Int(0));
+        store.insert(String::from("b"), Data::Int(1));
+        store.insert(String::from("c"), Data::Int(2));
+        let store = Store::new(store);
+
+        let keys: Vec<&String> = Iter::range(&store, String::from("a"), String::from("c"))
+            .keys()
+            .collect();
+        assert_eq!(vec!["a", "b"], keys);
+        assert_eq!(0,
+                   Iter::range(&store, String::from("c"), String::from("a")).count());
+    }
+
+    #[test]
+    fn test_expired() {
+        let mut store = Store::default();
+        store.insert(String::from("a"), Data::Int(0), 1);
+        store.insert(String::from("b"), Data::Int(1), 2);
+        store.insert(String::from("c"), Data::Int(2), 3);
+        store.set_deadline("a", Some(0), 4);
+        store.set_deadline("c", Some(0), 5);
+
+        let keys: Vec<&String> = Iter::all(&store).keys().collect();
+        assert_eq!(vec!["b"], keys);
+        let keys: Vec<&String> = Iter::all(&store).keys().rev().collect();
+        assert_eq!(vec!["b"], keys);
+    }
+}
@@ -1,17 +1,27 @@
 //! A tiny, embeddable, ACID compliant in-memory key/value database.
 
-#![feature(io)]
-#[macro_use]
+#[cfg_attr(test, macro_use)]
 extern crate serde_json;
+extern crate rpds;
 
 pub use self::database::*;
 pub use self::transaction::*;
 pub use self::error::*;
 pub use self::data::*;
 pub use self::persist::*;
+pub use self::iter::*;
+pub use self::sorted_set::*;
 
 mod database;
 mod error;
 mod transaction;
 mod data;
 mod persist;
+mod iter;
+mod checksum;
+mod record;
+mod manifest;
+mod store;
+mod map;
+mod op;
+mod sorted_set;
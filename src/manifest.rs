@@ -0,0 +1,142 @@
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use error::*;
+
+const GENERATION_KEY: &str = "generation";
+const SNAPSHOT_KEY: &str = "snapshot";
+const SEGMENT_KEY: &str = "segment";
+
+// The manifest records which snapshot and log segments are current.
+// The data is loaded from the snapshot, then the segments are
+// replayed in order. Files are recorded by their names, relative to
+// the directory of the manifest.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Manifest {
+    // Increased by every checkpoint.
+    pub generation: u64,
+    pub snapshot: Option<String>,
+    pub segments: Vec<String>,
+}
+
+impl Manifest {
+    // Read the manifest at the given path, return `None` if it does
+    // not exist.
+    pub fn read(path: &str) -> Result<Option<Manifest>> {
+        let mut content = String::new();
+        match fs::File::open(path) {
+            Ok(mut file) => file.read_to_string(&mut content)?,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(Error::from(err)),
+        };
+
+        let mut manifest = Manifest::default();
+        for line in content.lines() {
+            let mut parts = line.splitn(2, ' ');
+            match (parts.next(), parts.next()) {
+                (Some(GENERATION_KEY), Some(generation)) => {
+                    manifest.generation = generation.parse()?
+                }
+                (Some(SNAPSHOT_KEY), Some(name)) => manifest.snapshot = Some(String::from(name)),
+                (Some(SEGMENT_KEY), Some(name)) => manifest.segments.push(String::from(name)),
+                _ => return Err(Error::new(ErrorKind::InvalidSerializedString)),
+            }
+        }
+        Ok(Some(manifest))
+    }
+
+    // Atomically replace the manifest at the given path.
+    pub fn write(&self, path: &str) -> Result<()> {
+        let mut content = format!("{} {}\n", GENERATION_KEY, self.generation);
+        if let Some(ref snapshot) = self.snapshot {
+            content.push_str(&format!("{} {}\n", SNAPSHOT_KEY, snapshot));
+        }
+        for segment in &self.segments {
+            content.push_str(&format!("{} {}\n", SEGMENT_KEY, segment));
+        }
+
+        let tmp_path = format!("{}.tmp", path);
+        {
+            let mut file = fs::File::create(&tmp_path)?;
+            file.write_all(content.as_bytes())?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp_path, path)?;
+        sync_dir(path);
+        Ok(())
+    }
+}
+
+// Return the path of a file recorded in the manifest.
+pub fn resolve(manifest_path: &str, name: &str) -> String {
+    match Path::new(manifest_path).parent() {
+        Some(dir) => String::from(dir.join(name).to_str().unwrap()),
+        None => String::from(name),
+    }
+}
+
+// Return the name of a file to be recorded in the manifest.
+pub fn file_name(path: &str) -> String {
+    match Path::new(path).file_name() {
+        Some(name) => String::from(name.to_str().unwrap()),
+        None => String::from(path),
+    }
+}
+
+// Persist the renames in the directory of the given file, which is not
+// supported on every platform.
+pub fn sync_dir(path: &str) {
+    let dir = match Path::new(path).parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+    if let Ok(dir) = fs::File::open(dir) {
+        let _ = dir.sync_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn get_cdb_path(name: &str) -> String {
+        let mut cdb_path = env::current_dir().unwrap();
+        cdb_path.push(String::from("tests/") + name);
+        String::from(cdb_path.as_path().to_str().unwrap())
+    }
+
+    #[test]
+    fn test_read_write() {
+        let path = get_cdb_path("test_manifest.cdb.manifest");
+        let _ = fs::remove_file(&path);
+        assert_eq!(None, Manifest::read(&path).unwrap());
+
+        let manifest = Manifest {
+            generation: 3,
+            snapshot: Some(String::from("test_manifest.cdb.snapshot.3")),
+            segments: vec![String::from("test_manifest.cdb")],
+        };
+        manifest.write(&path).unwrap();
+        assert_eq!(Some(manifest), Manifest::read(&path).unwrap());
+        assert!(fs::metadata(format!("{}.tmp", path)).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_read_invalid() {
+        let path = get_cdb_path("test_manifest_invalid.cdb.manifest");
+        fs::write(&path, "generation x\n").unwrap();
+        assert!(Manifest::read(&path).is_err());
+        fs::write(&path, "unknown\n").unwrap();
+        assert!(Manifest::read(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!("dir/a.cdb", resolve("dir/a.cdb.manifest", "a.cdb"));
+        assert_eq!("a.cdb", resolve("a.cdb.manifest", "a.cdb"));
+        assert_eq!("a.cdb", file_name("dir/a.cdb"));
+    }
+}
@@ -0,0 +1,183 @@
+use std::borrow::Borrow;
+use std::collections::Bound::{self, Included, Excluded};
+use std::fmt;
+use std::iter::{self, FromIterator};
+use rpds::RedBlackTreeMapSync;
+
+// An ordered map whose nodes are shared by its clones, so cloning it
+// is cheap, and a change copies only the nodes on the path to the key.
+// It wraps the persistent map of `rpds` with the API of `BTreeMap`.
+pub struct Map<K, V> {
+    inner: RedBlackTreeMapSync<K, V>,
+}
+
+// An iterator over the items of a map whose key is in a range, in
+// order.
+pub struct Range<'a, K: 'a, V: 'a> {
+    inner: Box<dyn DoubleEndedIterator<Item = (&'a K, &'a V)> + 'a>,
+}
+
+impl<K: Ord + Clone, V: Clone> Map<K, V> {
+    pub fn new() -> Map<K, V> {
+        Map { inner: RedBlackTreeMapSync::new_sync() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.size()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+        where K: Borrow<Q>,
+              Q: Ord + ?Sized
+    {
+        self.inner.get(key)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+        where K: Borrow<Q>,
+              Q: Ord + ?Sized
+    {
+        self.inner.contains_key(key)
+    }
+
+    // Set the value of the key, return the previous one.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let previous = self.inner.get(&key).cloned();
+        self.inner.insert_mut(key, value);
+        previous
+    }
+
+    // Remove the key, return its value.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+        where K: Borrow<Q>,
+              Q: Ord + ?Sized
+    {
+        let previous = self.inner.get(key).cloned();
+        if previous.is_some() {
+            self.inner.remove_mut(key);
+        }
+        previous
+    }
+
+    pub fn iter(&self) -> Range<'_, K, V> {
+        Range { inner: Box::new(self.inner.iter()) }
+    }
+
+    // Iterate over the items whose key is in the bounds, it is empty if
+    // the start is after the end.
+    pub fn range(&self, start: Bound<K>, end: Bound<K>) -> Range<'_, K, V> {
+        // `rpds` panics on such bounds instead.
+        let empty = match (&start, &end) {
+            (Included(start), Included(end)) => start > end,
+            (Included(start), Excluded(end)) |
+            (Excluded(start), Included(end)) |
+            (Excluded(start), Excluded(end)) => start >= end,
+            _ => false,
+        };
+        if empty {
+            return Range { inner: Box::new(iter::empty()) };
+        }
+        Range { inner: Box::new(self.inner.range((start, end))) }
+    }
+}
+
+impl<'a, K, V> Iterator for Range<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        self.inner.next()
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Range<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+        self.inner.next_back()
+    }
+}
+
+impl<K: Ord, V> Clone for Map<K, V> {
+    fn clone(&self) -> Map<K, V> {
+        Map { inner: self.inner.clone() }
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> Default for Map<K, V> {
+    fn default() -> Map<K, V> {
+        Map::new()
+    }
+}
+
+impl<K: Ord + Clone, V: Clone + PartialEq> PartialEq for Map<K, V> {
+    fn eq(&self, other: &Map<K, V>) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl<K: Ord + Clone + fmt::Debug, V: Clone + fmt::Debug> fmt::Debug for Map<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> FromIterator<(K, V)> for Map<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Map<K, V> {
+        let mut map = Map::new();
+        for (key, value) in iter {
+            map.insert(key, value);
+        }
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::Bound::Unbounded;
+
+    fn keys<'a, I: Iterator<Item = (&'a i32, &'a i32)>>(range: I) -> Vec<i32> {
+        range.map(|(&key, _)| key).collect()
+    }
+
+    #[test]
+    fn test_insert_remove() {
+        let mut map = Map::new();
+        assert_eq!(None, map.insert(1, 1));
+        assert_eq!(Some(1), map.insert(1, 2));
+        assert_eq!(None, map.insert(2, 3));
+        assert_eq!(2, map.len());
+        assert_eq!(Some(2), map.remove(&1));
+        assert_eq!(None, map.remove(&1));
+        assert_eq!(Some(&3), map.get(&2));
+        assert_eq!(1, map.len());
+    }
+
+    #[test]
+    fn test_shared() {
+        let mut map: Map<String, i32> = (0..100).map(|i| (i.to_string(), i)).collect();
+        let snapshot = map.clone();
+        map.insert(String::from("50"), -1);
+        map.remove("10");
+        assert_eq!(Some(&50), snapshot.get("50"));
+        assert_eq!(Some(&10), snapshot.get("10"));
+        assert_eq!(100, snapshot.len());
+        assert_eq!(Some(&-1), map.get("50"));
+        assert_eq!(None, map.get("10"));
+        assert_eq!(99, map.len());
+    }
+
+    #[test]
+    fn test_range() {
+        let map: Map<i32, i32> = (0..10).map(|i| (i * 2, i)).collect();
+        assert_eq!(vec![4, 6, 8], keys(map.range(Included(3), Excluded(10))));
+        assert_eq!(vec![16, 18], keys(map.range(Included(16), Unbounded)));
+        assert_eq!(vec![8, 6, 4], keys(map.range(Included(3), Excluded(10)).rev()));
+        assert!(keys(map.range(Included(10), Excluded(3))).is_empty());
+        assert!(keys(map.range(Excluded(4), Excluded(4))).is_empty());
+        assert!(keys(map.range(Included(4), Excluded(4))).is_empty());
+        assert_eq!(vec![4], keys(map.range(Included(4), Included(4))));
+    }
+}
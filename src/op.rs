@@ -0,0 +1,593 @@
+use std::collections::{VecDeque, BTreeMap, BTreeSet};
+use data::*;
+use error::*;
+use sorted_set::SortedSet;
+use record::{encode_u32, encode_u64, decode_u32, decode_u64};
+
+// The tags of the operations in the log.
+const PUSH_TAG: u8 = 1;
+const POP_TAG: u8 = 2;
+const LIST_REMOVE_TAG: u8 = 3;
+const TRIM_TAG: u8 = 4;
+const HASH_SET_TAG: u8 = 5;
+const HASH_REMOVE_TAG: u8 = 6;
+const SET_ADD_TAG: u8 = 7;
+const SET_REMOVE_TAG: u8 = 8;
+const SORTED_ADD_TAG: u8 = 9;
+const SORTED_REMOVE_TAG: u8 = 10;
+const INCR_BY_TAG: u8 = 11;
+const INCR_BY_FLOAT_TAG: u8 = 12;
+const DECR_BY_TAG: u8 = 13;
+
+// The end of a list.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+// An operation which changes the value of one key in place, so only
+// the operation is written to the log instead of the whole value.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Op {
+    // Push the item to the list.
+    Push(Side, Data),
+    // Pop an item from the list.
+    Pop(Side),
+    // Remove the items equal to the data from the list. The count is
+    // like `WriteTransaction::lrem`.
+    ListRemove(i64, Data),
+    // Only keep the items in the range of the list.
+    Trim(i64, i64),
+    // Set the value of the field of the hash.
+    HashSet(String, Data),
+    // Remove the field from the hash.
+    HashRemove(String),
+    // Add the member to the set.
+    SetAdd(String),
+    // Remove the member from the set.
+    SetRemove(String),
+    // Set the score of the member of the sorted set.
+    SortedAdd(String, f64),
+    // Remove the member from the sorted set.
+    SortedRemove(String),
+    // Add the delta to the integer, which starts from 0.
+    IncrBy(i64),
+    // Add the delta to the float or the integer, which starts from 0.
+    IncrByFloat(f64),
+    // Subtract the delta from the integer, which starts from 0.
+    DecrBy(i64),
+}
+
+impl Op {
+    // Apply the operation to the value, which is `None` if the key
+    // does not exist. An emptied collection is removed. The value is
+    // not changed if the operation fails.
+    pub fn apply(self, value: &mut Option<Data>) -> Result<()> {
+        match self {
+            Op::Push(side, item) => {
+                let list = list_mut(value)?;
+                match side {
+                    Side::Left => list.push_front(item),
+                    Side::Right => list.push_back(item),
+                }
+            }
+            Op::Pop(side) => {
+                let list = list_mut(value)?;
+                match side {
+                    Side::Left => list.pop_front(),
+                    Side::Right => list.pop_back(),
+                };
+            }
+            Op::ListRemove(count, item) => {
+                let list = list_mut(value)?;
+                let limit = if count == 0 {
+                    list.len()
+                } else {
+                    count.unsigned_abs() as usize
+                };
+                let mut removed = 0;
+                if count >= 0 {
+                    list.retain(|data| if removed < limit && *data == item {
+                                    removed += 1;
+                                    false
+                                } else {
+                                    true
+                                });
+                } else {
+                    let mut kept = VecDeque::with_capacity(list.len());
+                    while let Some(data) = list.pop_back() {
+                        if removed < limit && data == item {
+                            removed += 1;
+                        } else {
+                            kept.push_front(data);
+                        }
+                    }
+                    *list = kept;
+                }
+            }
+            Op::Trim(start, stop) => {
+                let list = list_mut(value)?;
+                match range_of(start, stop, list.len()) {
+                    Some((start, stop)) => {
+                        list.truncate(stop + 1);
+                        list.drain(..start);
+                    }
+                    None => list.clear(),
+                }
+            }
+            Op::HashSet(field, data) => {
+                hash_mut(value)?.insert(field, data);
+            }
+            Op::HashRemove(field) => {
+                hash_mut(value)?.remove(&field);
+            }
+            Op::SetAdd(member) => {
+                set_mut(value)?.insert(member);
+            }
+            Op::SetRemove(member) => {
+                set_mut(value)?.remove(&member);
+            }
+            Op::SortedAdd(member, score) => {
+                sorted_set_mut(value)?.insert(member, score);
+            }
+            Op::SortedRemove(member) => {
+                sorted_set_mut(value)?.remove(&member);
+            }
+            Op::IncrBy(delta) => change_int(value, |int| int.checked_add(delta))?,
+            Op::DecrBy(delta) => change_int(value, |int| int.checked_sub(delta))?,
+            Op::IncrByFloat(delta) => {
+                let current = match *value {
+                    Some(Data::Float(float)) => float,
+                    Some(Data::Int(int)) => int as f64,
+                    Some(_) => return Err(Error::new(ErrorKind::WrongType)),
+                    None => 0.0,
+                };
+                let result = current + delta;
+                if !result.is_finite() {
+                    return Err(Error::new(ErrorKind::Overflow));
+                }
+                *value = Some(Data::Float(result));
+            }
+        }
+
+        let is_empty = match *value {
+            Some(Data::List(ref list)) => list.is_empty(),
+            Some(Data::Hash(ref hash)) => hash.is_empty(),
+            Some(Data::Set(ref set)) => set.is_empty(),
+            Some(Data::SortedSet(ref set)) => set.is_empty(),
+            _ => false,
+        };
+        if is_empty {
+            *value = None;
+        }
+        Ok(())
+    }
+
+    // Encode the operation into the payload of a log record.
+    pub fn encode(self) -> Vec<u8> {
+        match self {
+            Op::Push(side, item) => {
+                let mut bytes = vec![PUSH_TAG, encode_side(side)];
+                bytes.extend(item.into_bytes());
+                bytes
+            }
+            Op::Pop(side) => vec![POP_TAG, encode_side(side)],
+            Op::ListRemove(count, item) => {
+                let mut bytes = vec![LIST_REMOVE_TAG];
+                bytes.extend(encode_u64(count as u64));
+                bytes.extend(item.into_bytes());
+                bytes
+            }
+            Op::Trim(start, stop) => {
+                let mut bytes = vec![TRIM_TAG];
+                bytes.extend(encode_u64(start as u64));
+                bytes.extend(encode_u64(stop as u64));
+                bytes
+            }
+            Op::HashSet(field, data) => {
+                let mut bytes = vec![HASH_SET_TAG];
+                bytes.extend(encode_u32(field.len() as u32));
+                bytes.extend(field.into_bytes());
+                bytes.extend(data.into_bytes());
+                bytes
+            }
+            Op::HashRemove(field) => with_tag(HASH_REMOVE_TAG, field),
+            Op::SetAdd(member) => with_tag(SET_ADD_TAG, member),
+            Op::SetRemove(member) => with_tag(SET_REMOVE_TAG, member),
+            Op::SortedAdd(member, score) => {
+                let mut bytes = vec![SORTED_ADD_TAG];
+                bytes.extend(encode_u64(score.to_bits()));
+                bytes.extend(member.into_bytes());
+                bytes
+            }
+            Op::SortedRemove(member) => with_tag(SORTED_REMOVE_TAG, member),
+            Op::IncrBy(delta) => {
+                let mut bytes = vec![INCR_BY_TAG];
+                bytes.extend(encode_u64(delta as u64));
+                bytes
+            }
+            Op::IncrByFloat(delta) => {
+                let mut bytes = vec![INCR_BY_FLOAT_TAG];
+                bytes.extend(encode_u64(delta.to_bits()));
+                bytes
+            }
+            Op::DecrBy(delta) => {
+                let mut bytes = vec![DECR_BY_TAG];
+                bytes.extend(encode_u64(delta as u64));
+                bytes
+            }
+        }
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Op> {
+        if bytes.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidSerializedString));
+        }
+        let payload = &bytes[1..];
+        match bytes[0] {
+            PUSH_TAG if !payload.is_empty() => {
+                let item = Data::try_from_bytes(payload[1..].to_vec())?;
+                Ok(Op::Push(decode_side(payload[0])?, item))
+            }
+            POP_TAG if payload.len() == 1 => Ok(Op::Pop(decode_side(payload[0])?)),
+            LIST_REMOVE_TAG if payload.len() >= 8 => {
+                let item = Data::try_from_bytes(payload[8..].to_vec())?;
+                Ok(Op::ListRemove(decode_u64(&payload[..8]) as i64, item))
+            }
+            TRIM_TAG if payload.len() == 16 => {
+                Ok(Op::Trim(decode_u64(&payload[..8]) as i64,
+                            decode_u64(&payload[8..]) as i64))
+            }
+            HASH_SET_TAG if payload.len() >= 4 => {
+                let field_len = decode_u32(&payload[..4]) as usize;
+                if payload.len() < 4 + field_len {
+                    return Err(Error::new(ErrorKind::InvalidSerializedString));
+                }
+                let field = String::from_utf8(payload[4..4 + field_len].to_vec())?;
+                let data = Data::try_from_bytes(payload[4 + field_len..].to_vec())?;
+                Ok(Op::HashSet(field, data))
+            }
+            HASH_REMOVE_TAG => Ok(Op::HashRemove(String::from_utf8(payload.to_vec())?)),
+            SET_ADD_TAG => Ok(Op::SetAdd(String::from_utf8(payload.to_vec())?)),
+            SET_REMOVE_TAG => Ok(Op::SetRemove(String::from_utf8(payload.to_vec())?)),
+            SORTED_ADD_TAG if payload.len() >= 8 => {
+                let member = String::from_utf8(payload[8..].to_vec())?;
+                Ok(Op::SortedAdd(member, f64::from_bits(decode_u64(&payload[..8]))))
+            }
+            SORTED_REMOVE_TAG => Ok(Op::SortedRemove(String::from_utf8(payload.to_vec())?)),
+            INCR_BY_TAG if payload.len() == 8 => Ok(Op::IncrBy(decode_u64(payload) as i64)),
+            INCR_BY_FLOAT_TAG if payload.len() == 8 => {
+                Ok(Op::IncrByFloat(f64::from_bits(decode_u64(payload))))
+            }
+            DECR_BY_TAG if payload.len() == 8 => Ok(Op::DecrBy(decode_u64(payload) as i64)),
+            _ => Err(Error::new(ErrorKind::InvalidSerializedString)),
+        }
+    }
+}
+
+// Replace the integer of the value, which starts from 0, by the result
+// of the change. `ErrorKind::Overflow` is returned if there is no
+// result.
+fn change_int<F>(value: &mut Option<Data>, change: F) -> Result<()>
+    where F: FnOnce(i64) -> Option<i64>
+{
+    let current = match *value {
+        Some(Data::Int(int)) => int,
+        Some(_) => return Err(Error::new(ErrorKind::WrongType)),
+        None => 0,
+    };
+    match change(current) {
+        Some(result) => {
+            *value = Some(Data::Int(result));
+            Ok(())
+        }
+        None => Err(Error::new(ErrorKind::Overflow)),
+    }
+}
+
+// Return the list of the value, which is created if the key does not
+// exist.
+fn list_mut(value: &mut Option<Data>) -> Result<&mut VecDeque<Data>> {
+    if value.is_none() {
+        *value = Some(Data::List(VecDeque::new()));
+    }
+    match *value {
+        Some(Data::List(ref mut list)) => Ok(list),
+        _ => Err(Error::new(ErrorKind::WrongType)),
+    }
+}
+
+// Return the hash of the value, which is created if the key does not
+// exist.
+fn hash_mut(value: &mut Option<Data>) -> Result<&mut BTreeMap<String, Data>> {
+    if value.is_none() {
+        *value = Some(Data::Hash(BTreeMap::new()));
+    }
+    match *value {
+        Some(Data::Hash(ref mut hash)) => Ok(hash),
+        _ => Err(Error::new(ErrorKind::WrongType)),
+    }
+}
+
+// Return the set of the value, which is created if the key does not
+// exist.
+fn set_mut(value: &mut Option<Data>) -> Result<&mut BTreeSet<String>> {
+    if value.is_none() {
+        *value = Some(Data::Set(BTreeSet::new()));
+    }
+    match *value {
+        Some(Data::Set(ref mut set)) => Ok(set),
+        _ => Err(Error::new(ErrorKind::WrongType)),
+    }
+}
+
+// Return the sorted set of the value, which is created if the key does
+// not exist.
+fn sorted_set_mut(value: &mut Option<Data>) -> Result<&mut SortedSet> {
+    if value.is_none() {
+        *value = Some(Data::SortedSet(SortedSet::new()));
+    }
+    match *value {
+        Some(Data::SortedSet(ref mut set)) => Ok(set),
+        _ => Err(Error::new(ErrorKind::WrongType)),
+    }
+}
+
+// Return the list of the data, or `None` if the key does not exist.
+pub fn as_list(data: Option<&Data>) -> Result<Option<&VecDeque<Data>>> {
+    match data {
+        Some(Data::List(list)) => Ok(Some(list)),
+        Some(_) => Err(Error::new(ErrorKind::WrongType)),
+        None => Ok(None),
+    }
+}
+
+// Return the hash of the data, or `None` if the key does not exist.
+pub fn as_hash(data: Option<&Data>) -> Result<Option<&BTreeMap<String, Data>>> {
+    match data {
+        Some(Data::Hash(hash)) => Ok(Some(hash)),
+        Some(_) => Err(Error::new(ErrorKind::WrongType)),
+        None => Ok(None),
+    }
+}
+
+// Return the set of the data, or `None` if the key does not exist.
+pub fn as_set(data: Option<&Data>) -> Result<Option<&BTreeSet<String>>> {
+    match data {
+        Some(Data::Set(set)) => Ok(Some(set)),
+        Some(_) => Err(Error::new(ErrorKind::WrongType)),
+        None => Ok(None),
+    }
+}
+
+// Return the sorted set of the data, or `None` if the key does not
+// exist.
+pub fn as_sorted_set(data: Option<&Data>) -> Result<Option<&SortedSet>> {
+    match data {
+        Some(Data::SortedSet(set)) => Ok(Some(set)),
+        Some(_) => Err(Error::new(ErrorKind::WrongType)),
+        None => Ok(None),
+    }
+}
+
+// Return the indexes of the first and the last items in the range of
+// [start, stop] of a sequence of the length, or `None` if the range is
+// empty. Negative indexes count from the end of the sequence, so -1 is
+// the last item.
+pub fn range_of(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 { start + len } else { start }.max(0);
+    let stop = if stop < 0 { stop + len } else { stop }.min(len - 1);
+    if start > stop || start >= len {
+        return None;
+    }
+    Some((start as usize, stop as usize))
+}
+
+// Encode an operation which only has a string.
+fn with_tag(tag: u8, string: String) -> Vec<u8> {
+    let mut bytes = vec![tag];
+    bytes.extend(string.into_bytes());
+    bytes
+}
+
+fn encode_side(side: Side) -> u8 {
+    match side {
+        Side::Left => 0,
+        Side::Right => 1,
+    }
+}
+
+fn decode_side(byte: u8) -> Result<Side> {
+    match byte {
+        0 => Ok(Side::Left),
+        1 => Ok(Side::Right),
+        _ => Err(Error::new(ErrorKind::InvalidSerializedString)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(items: &[i64]) -> Option<Data> {
+        Some(Data::List(items.iter().map(|&item| Data::Int(item)).collect()))
+    }
+
+    fn apply(op: Op, items: &[i64]) -> Option<Data> {
+        let mut value = list(items);
+        op.apply(&mut value).unwrap();
+        value
+    }
+
+    #[test]
+    fn test_push_pop() {
+        let mut value = None;
+        Op::Push(Side::Left, Data::Int(2)).apply(&mut value).unwrap();
+        Op::Push(Side::Left, Data::Int(1)).apply(&mut value).unwrap();
+        Op::Push(Side::Right, Data::Int(3)).apply(&mut value).unwrap();
+        assert_eq!(list(&[1, 2, 3]), value);
+
+        Op::Pop(Side::Right).apply(&mut value).unwrap();
+        Op::Pop(Side::Left).apply(&mut value).unwrap();
+        assert_eq!(list(&[2]), value);
+        Op::Pop(Side::Left).apply(&mut value).unwrap();
+        assert_eq!(None, value);
+        Op::Pop(Side::Left).apply(&mut value).unwrap();
+        assert_eq!(None, value);
+    }
+
+    #[test]
+    fn test_wrong_type() {
+        let mut value = Some(Data::Int(1));
+        match Op::Push(Side::Left, Data::Int(2)).apply(&mut value) {
+            Err(Error { kind: ErrorKind::WrongType }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(Some(Data::Int(1)), value);
+        assert!(as_list(value.as_ref()).is_err());
+    }
+
+    #[test]
+    fn test_list_remove() {
+        let items = [1, 2, 1, 3, 1];
+        assert_eq!(list(&[2, 3]), apply(Op::ListRemove(0, Data::Int(1)), &items));
+        assert_eq!(list(&[2, 3, 1]), apply(Op::ListRemove(2, Data::Int(1)), &items));
+        assert_eq!(list(&[1, 2, 3]), apply(Op::ListRemove(-2, Data::Int(1)), &items));
+        assert_eq!(list(&items), apply(Op::ListRemove(0, Data::Int(4)), &items));
+        assert_eq!(None, apply(Op::ListRemove(0, Data::Int(1)), &[1, 1]));
+    }
+
+    #[test]
+    fn test_trim() {
+        let items = [1, 2, 3, 4];
+        assert_eq!(list(&[2, 3]), apply(Op::Trim(1, 2), &items));
+        assert_eq!(list(&[3, 4]), apply(Op::Trim(-2, -1), &items));
+        assert_eq!(list(&items), apply(Op::Trim(-10, 10), &items));
+        assert_eq!(None, apply(Op::Trim(3, 1), &items));
+        assert_eq!(None, apply(Op::Trim(5, 10), &items));
+    }
+
+    #[test]
+    fn test_hash() {
+        let mut value = None;
+        Op::HashSet(String::from("a"), Data::Int(1)).apply(&mut value).unwrap();
+        Op::HashSet(String::from("b"), Data::Int(2)).apply(&mut value).unwrap();
+        Op::HashSet(String::from("a"), Data::Int(3)).apply(&mut value).unwrap();
+        Op::HashRemove(String::from("b")).apply(&mut value).unwrap();
+        Op::HashRemove(String::from("c")).apply(&mut value).unwrap();
+        let hash = as_hash(value.as_ref()).unwrap().unwrap();
+        assert_eq!(vec![(&String::from("a"), &Data::Int(3))],
+                   hash.iter().collect::<Vec<_>>());
+
+        Op::HashRemove(String::from("a")).apply(&mut value).unwrap();
+        assert_eq!(None, value);
+        assert!(Op::HashSet(String::from("a"), Data::Int(1)).apply(&mut list(&[1])).is_err());
+        assert!(as_hash(list(&[1]).as_ref()).is_err());
+    }
+
+    #[test]
+    fn test_sets() {
+        let mut value = None;
+        Op::SetAdd(String::from("a")).apply(&mut value).unwrap();
+        Op::SetAdd(String::from("b")).apply(&mut value).unwrap();
+        Op::SetRemove(String::from("a")).apply(&mut value).unwrap();
+        let set = as_set(value.as_ref()).unwrap().unwrap();
+        assert_eq!(vec!["b"], set.iter().collect::<Vec<_>>());
+        Op::SetRemove(String::from("b")).apply(&mut value).unwrap();
+        assert_eq!(None, value);
+
+        Op::SortedAdd(String::from("a"), 2.0).apply(&mut value).unwrap();
+        Op::SortedAdd(String::from("b"), 1.0).apply(&mut value).unwrap();
+        assert!(Op::SetAdd(String::from("a")).apply(&mut value).is_err());
+        assert!(as_set(value.as_ref()).is_err());
+        let set = as_sorted_set(value.as_ref()).unwrap().unwrap();
+        assert_eq!(Some(0), set.rank("b"));
+        Op::SortedRemove(String::from("a")).apply(&mut value).unwrap();
+        Op::SortedRemove(String::from("b")).apply(&mut value).unwrap();
+        assert_eq!(None, value);
+    }
+
+    #[test]
+    fn test_incr_by() {
+        let mut value = None;
+        Op::IncrBy(5).apply(&mut value).unwrap();
+        Op::IncrBy(-7).apply(&mut value).unwrap();
+        assert_eq!(Some(Data::Int(-2)), value);
+        Op::IncrBy(i64::MIN + 2).apply(&mut value).unwrap();
+        match Op::IncrBy(-1).apply(&mut value) {
+            Err(Error { kind: ErrorKind::Overflow }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(Some(Data::Int(i64::MIN)), value);
+        assert!(Op::IncrBy(1).apply(&mut Some(Data::Float(1.0))).is_err());
+        assert!(Op::IncrBy(1).apply(&mut list(&[1])).is_err());
+    }
+
+    #[test]
+    fn test_decr_by() {
+        let mut value = None;
+        Op::DecrBy(3).apply(&mut value).unwrap();
+        assert_eq!(Some(Data::Int(-3)), value);
+        let mut value = Some(Data::Int(-1));
+        Op::DecrBy(i64::MIN).apply(&mut value).unwrap();
+        assert_eq!(Some(Data::Int(i64::MAX)), value);
+        match Op::DecrBy(-1).apply(&mut value) {
+            Err(Error { kind: ErrorKind::Overflow }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert!(Op::DecrBy(i64::MIN).apply(&mut None).is_err());
+        assert!(Op::DecrBy(1).apply(&mut Some(Data::Float(1.0))).is_err());
+    }
+
+    #[test]
+    fn test_incr_by_float() {
+        let mut value = None;
+        Op::IncrByFloat(1.5).apply(&mut value).unwrap();
+        assert_eq!(Some(Data::Float(1.5)), value);
+        let mut value = Some(Data::Int(2));
+        Op::IncrByFloat(0.5).apply(&mut value).unwrap();
+        assert_eq!(Some(Data::Float(2.5)), value);
+        assert!(Op::IncrByFloat(f64::MAX).apply(&mut Some(Data::Float(f64::MAX))).is_err());
+        assert!(Op::IncrByFloat(f64::NAN).apply(&mut value).is_err());
+        assert_eq!(Some(Data::Float(2.5)), value);
+        assert!(Op::IncrByFloat(1.0).apply(&mut Some(Data::Null)).is_err());
+    }
+
+    #[test]
+    fn test_range_of() {
+        assert_eq!(Some((0, 3)), range_of(0, -1, 4));
+        assert_eq!(Some((2, 3)), range_of(-2, 100, 4));
+        assert_eq!(None, range_of(0, -1, 0));
+        assert_eq!(None, range_of(2, 1, 4));
+        assert_eq!(None, range_of(0, -5, 4));
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let ops = vec![Op::Push(Side::Left, Data::Bytes(vec![0, 1])),
+                       Op::Push(Side::Right, Data::Int(1)),
+                       Op::Pop(Side::Right),
+                       Op::ListRemove(-2, Data::String(String::from("a"))),
+                       Op::Trim(-3, 4),
+                       Op::HashSet(String::from("字段"), Data::Int(1)),
+                       Op::HashRemove(String::from("字段")),
+                       Op::SetAdd(String::from("a")),
+                       Op::SetRemove(String::from("")),
+                       Op::SortedAdd(String::from("a"), -1.5),
+                       Op::SortedRemove(String::from("a")),
+                       Op::IncrBy(i64::MIN),
+                       Op::IncrByFloat(-0.25),
+                       Op::DecrBy(i64::MIN)];
+        for op in ops {
+            assert_eq!(op, Op::decode(&op.clone().encode()).unwrap());
+        }
+        assert!(Op::decode(&[]).is_err());
+        assert!(Op::decode(&[POP_TAG, 2]).is_err());
+        assert!(Op::decode(&[TRIM_TAG, 0]).is_err());
+        assert!(Op::decode(&[HASH_SET_TAG, 9, 0, 0, 0, b'a']).is_err());
+        assert!(Op::decode(&[SORTED_ADD_TAG, 0]).is_err());
+        assert!(Op::decode(&[INCR_BY_TAG, 0]).is_err());
+    }
+}
@@ -1,24 +1,61 @@
-use std::collections::*;
 use std::fs;
-use std::io::{Write, Read};
+use std::time::Duration;
+use std::io::{self, Write, Read, BufReader, BufWriter};
 use data::*;
 use error::*;
+use record::*;
+use manifest::*;
+use store::Store;
+use op::Op;
+use iter::Iter;
 
-const CR: &'static str = "\r";
-const LF: &'static str = "\n";
-const CRLF: &'static str = "\r\n";
-const SET_PREFIX: &'static str = "$";
-const REMOVE_PREFIX: &'static str = "#";
+// The header of the file in the binary format, which is the magic
+// bytes followed by the version of the format. Files without it are
+// in the text format written by the earlier versions.
+const MAGIC: &[u8] = b"\x89CDB";
+const FORMAT_VERSION: u8 = 2;
+const HEADER_LEN: u64 = 5;
 
-macro_rules! serialize_set_template { () => ("{prefix}{key_len}{crlf}{key}{val_len}{crlf}{value}") }
-macro_rules! serialize_remove_template { () => ("{prefix}{key_len}{crlf}{key}") }
-
-// The sync policy of the database. Never or every update
-// transaction.
-#[derive(Debug, PartialEq)]
+// The sync policy of the database. Never, every update
+// transaction, or periodically in the background.
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum SyncPolicy {
     Never,
     Always,
+    Every(Duration),
+}
+
+// How to deal with a damaged file on load. Either refuse to load it,
+// or truncate the file after the last valid record.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RecoveryMode {
+    Strict,
+    TruncateTail,
+}
+
+// What was dropped from the file when it is loaded.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RecoveryReport {
+    pub dropped_bytes: u64,
+    pub dropped_records: usize,
+}
+
+// When the persisted data should be compacted. `Auto` compacts the
+// file once it is at least `min_size` bytes and `ratio` times larger
+// than the live data.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CompactPolicy {
+    Never,
+    Auto { min_size: u64, ratio: f64 },
+}
+
+// When the log should be checkpointed. `Auto` dumps the live data to
+// a snapshot and starts a fresh log once the log written since the
+// last checkpoint is at least `log_size` bytes.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CheckpointPolicy {
+    Never,
+    Auto { log_size: u64 },
 }
 
 // Whether this database should persist its data to the disk.
@@ -28,197 +65,737 @@ pub enum PersistType {
     File(String),
 }
 
-pub trait Persistable {
-    fn set(&mut self, String, Data) -> Result<()>;
-    fn remove(&mut self, String) -> Result<()>;
-    fn load(&mut self) -> Result<BTreeMap<String, Data>>;
+// How the file of a FileStore should be opened.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum OpenMode {
+    // Create a new file, fail if it already exists.
+    CreateNew,
+    // Open an existing file, fail if it does not exist.
+    OpenExisting,
+    // Open the file if it exists, otherwise create it.
+    OpenOrCreate,
+    // Create the file if needed and discard all its contents.
+    Truncate,
+}
+
+pub trait Persistable: Send {
+    fn set(&mut self, key: String, data: Data) -> Result<()>;
+    fn remove(&mut self, key: String) -> Result<()>;
+    // Remove all the keys. Unlike `clear`, which drops everything
+    // persisted, it is persisted as a record itself.
+    fn remove_all(&mut self) -> Result<()>;
+    // Mark the begin and the commit of a transaction, the records
+    // between them are applied atomically on load. The transaction is
+    // identified by the last version handed out, so the keys could be
+    // loaded with a version newer than all of them.
+    fn begin(&mut self, version: u64) -> Result<()>;
+    fn commit(&mut self) -> Result<()>;
+    // Set the deadline of the key, in milliseconds since the Unix
+    // epoch, or let it live forever.
+    fn expire(&mut self, key: String, deadline: u64) -> Result<()>;
+    fn persist(&mut self, key: String) -> Result<()>;
+    // Change the value of the key in place by the operation.
+    fn apply(&mut self, key: String, op: Op) -> Result<()>;
+    fn load(&mut self) -> Result<Store>;
     fn clear(&mut self) -> Result<()>;
+    fn sync(&mut self) -> Result<()>;
+    // Release the lock of the persisted data.
+    fn close(&mut self) -> Result<()>;
+    // Rewrite the persisted data with only the live data.
+    fn compact(&mut self, store: &Store) -> Result<()>;
+    // Dump the live data to a snapshot and start a fresh log, so only
+    // the log written since then is replayed on load.
+    fn checkpoint(&mut self, store: &Store) -> Result<()>;
+    // The size of the persisted data, and the size of the live data
+    // when it was last loaded or compacted.
+    fn size(&self) -> Result<u64>;
+    fn live_size(&self) -> u64;
+    // The size of the log written since the last checkpoint.
+    fn log_size(&self) -> Result<u64>;
 }
 
-#[derive(Debug, PartialEq)]
-enum LoadState {
-    Empty,
-    BeforeSetKeyCR,
-    BeforeSetKeyLF,
-    GetSetKey(usize),
-    BeforeSetValCR,
-    BeforeSetValLF,
-    GetSetVal(usize),
-    BeforeRemoveKeyCR,
-    BeforeRemoveKeyLF,
-    GetRemoveKey(usize),
+// The format of the file.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Format {
+    Text,
+    Binary,
+}
+
+// The records of a transaction which is not committed yet, and the
+// offset of its begin mark in the file.
+#[derive(Debug)]
+struct Batch {
+    id: u64,
+    offset: u64,
+    records: Vec<Record>,
+}
+
+// Replay the parsed records into a store. Records between a begin
+// and a commit mark are only applied once the commit mark is read.
+#[derive(Debug)]
+struct Replayer {
+    store: Store,
+    batch: Option<Batch>,
+    txn_id: u64,
+}
+
+// Where the replay of the records stopped.
+#[derive(Debug)]
+struct Replayed {
+    // Whether a record is damaged or partially written.
+    torn: bool,
+    // The offset of that record.
+    offset: u64,
 }
 
 #[derive(Debug)]
 pub struct FileStore {
     path: String,
     file: fs::File,
+    format: Format,
+    // The header is written along with the first record, so a cleared
+    // file stays empty.
+    header_written: bool,
+    // The id of the last transaction written to the file, which is
+    // the last version handed out when it was written.
+    txn_id: u64,
+    live_size: u64,
+    // The current snapshot and log segment. The file at `path` is
+    // always the log segment, which starts with the checkpoint mark
+    // of the generation after the first checkpoint.
+    manifest: Manifest,
+    snapshot_size: u64,
+    // The advisory lock held until the store is closed, which is
+    // shared if the store is read only.
+    lock: fs::File,
+    read_only: bool,
 }
 
 impl FileStore {
-    pub fn new(path: String) -> Result<FileStore> {
+    // Open the file at the given path. All new records are appended
+    // after the existing ones, which could be replayed by `load`. The
+    // format of the file is detected from its header. The file is
+    // locked exclusively, so it could not be opened by any other
+    // store until this one is closed.
+    pub fn new(path: String, mode: OpenMode) -> Result<FileStore> {
+        FileStore::open(path, mode, false)
+    }
+
+    // Open an existing file for reading only. The file is locked
+    // shared, so it could be opened by other readers at the same
+    // time, but not by a writer. Nothing is written or created, so it
+    // could be opened in a read only directory.
+    pub fn open_read_only(path: String) -> Result<FileStore> {
+        FileStore::open(path, OpenMode::OpenExisting, true)
+    }
+
+    fn open(path: String, mode: OpenMode, read_only: bool) -> Result<FileStore> {
+        // Lock before touching the file, so it is never truncated
+        // under another store.
+        let lock = lock(&path, read_only)?;
+        let mut options = fs::OpenOptions::new();
+        options.read(true).append(!read_only);
+        match mode {
+            OpenMode::CreateNew => options.create_new(true),
+            OpenMode::OpenExisting => &mut options,
+            OpenMode::OpenOrCreate | OpenMode::Truncate => options.create(true),
+        };
+
+        let file = options.open(&path)?;
+        if !read_only {
+            // A reader which found no lock file locks the file itself.
+            // It is kept locked until the file is replaced.
+            try_lock(&file, false)?;
+        }
+        let manifest_path = format!("{}.manifest", path);
+        if mode == OpenMode::Truncate {
+            file.set_len(0)?;
+            if let Some(manifest) = Manifest::read(&manifest_path)? {
+                fs::remove_file(&manifest_path)?;
+                if let Some(ref snapshot) = manifest.snapshot {
+                    let _ = fs::remove_file(resolve(&manifest_path, snapshot));
+                }
+            }
+        }
+
+        let manifest = Manifest::read(&manifest_path)?.unwrap_or_default();
+        if manifest.generation > 0 && manifest.segments != vec![file_name(&path)] {
+            return Err(Error::new(ErrorKind::InvalidSerializedString));
+        }
+        let snapshot_size = match manifest.snapshot {
+            Some(ref snapshot) => fs::metadata(resolve(&manifest_path, snapshot))?.len(),
+            None => 0,
+        };
+
+        let mut header = Vec::new();
+        (&file).take(HEADER_LEN).read_to_end(&mut header)?;
+        let (format, header_written) = if header.is_empty() {
+            (Format::Binary, false)
+        } else if header.len() < HEADER_LEN as usize {
+            // A header which is partially written is dropped on load.
+            if MAGIC.starts_with(&header) {
+                (Format::Binary, false)
+            } else {
+                (Format::Text, false)
+            }
+        } else if &header[..MAGIC.len()] == MAGIC {
+            if header[MAGIC.len()] != FORMAT_VERSION {
+                return Err(Error::new(ErrorKind::UnsupportedFormatVersion));
+            }
+            (Format::Binary, true)
+        } else {
+            (Format::Text, false)
+        };
+
         Ok(FileStore {
-               path: path.clone(),
-               file: fs::OpenOptions::new()
-                   .create(true)
-                   .truncate(true)
-                   .write(true)
-                   .read(true)
-                   .open(path)?,
+               path,
+               file,
+               format,
+               header_written,
+               txn_id: 0,
+               live_size: 0,
+               manifest,
+               snapshot_size,
+               lock,
+               read_only,
            })
     }
-}
 
-impl Persistable for FileStore {
-    fn set(&mut self, key: String, data: Data) -> Result<()> {
-        let value = data.into_string();
-        Ok(write!(self.file,
-                  serialize_set_template!(),
-                  prefix = SET_PREFIX,
-                  key_len = key.len(),
-                  val_len = value.len(),
-                  crlf = CRLF,
-                  key = key,
-                  value = value)?)
+    // Like `load`, but instead of failing on a damaged record, the
+    // file is truncated after the last valid record, and what was
+    // dropped is reported.
+    pub fn recover(&mut self) -> Result<(Store, Option<RecoveryReport>)> {
+        self.replay(true)
     }
 
-    fn remove(&mut self, key: String) -> Result<()> {
-        Ok(write!(self.file,
-                  serialize_remove_template!(),
-                  prefix = REMOVE_PREFIX,
-                  key_len = key.len(),
-                  crlf = CRLF,
-                  key = key)?)
-    }
-
-    fn load(&mut self) -> Result<BTreeMap<String, Data>> {
-        let mut btree: BTreeMap<String, Data> = BTreeMap::new();
-
-        let mut buffer = String::new();
-        let mut cache = String::new();
-        let mut state = LoadState::Empty;
-
-        for ch in fs::File::open(&self.path)?.chars() {
-            let ch = ch?;
-            let char_string = ch.to_string();
-            match state {
-                LoadState::Empty => {
-                    if char_string == SET_PREFIX {
-                        state = LoadState::BeforeSetKeyCR;
-                        continue;
-                    }
-                    if char_string == REMOVE_PREFIX {
-                        state = LoadState::BeforeRemoveKeyCR;
-                        continue;
-                    }
+    // Load the snapshot, then replay all the records in the log. A
+    // torn transaction at the end of the log is discarded and
+    // truncated, so new records could be appended after it. A file in
+    // the text format is kept as it is until it is compacted or
+    // written.
+    fn replay(&mut self, recover: bool) -> Result<(Store, Option<RecoveryReport>)> {
+        let mut replayer = Replayer::new();
+        if let Some(snapshot) = self.snapshot_path() {
+            if replay_binary(&snapshot, &mut replayer, false)?.torn {
+                return Err(Error::new(ErrorKind::InvalidSerializedString));
+            }
+        }
+
+        // The log is stale if the checkpoint is interrupted after the
+        // manifest is written, its records are already in the
+        // snapshot.
+        let generation = self.log_generation()?;
+        if generation > self.manifest.generation {
+            return Err(Error::new(ErrorKind::InvalidSerializedString));
+        }
+        if generation < self.manifest.generation {
+            if self.read_only {
+                replayer.store.set_base_version(replayer.txn_id);
+                return Ok((replayer.store, None));
+            }
+            self.reset_log()?;
+        }
+
+        let replayed = match self.format {
+            Format::Text => self.replay_text(&mut replayer, recover)?,
+            Format::Binary => replay_binary(&self.path, &mut replayer, recover)?,
+        };
+
+        let len = self.file.metadata()?.len();
+        let mut valid_len = if replayed.torn { replayed.offset } else { len };
+        let mut dropped_records = if replayed.torn { 1 } else { 0 };
+        match replayer.batch.take() {
+            Some(batch) => {
+                valid_len = batch.offset;
+                dropped_records += batch.records.len() + 1;
+            }
+            None => {
+                if replayed.torn && !recover {
                     return Err(Error::new(ErrorKind::InvalidSerializedString));
                 }
-                LoadState::BeforeSetKeyCR => {
-                    if char_string == CR {
-                        state = LoadState::BeforeSetKeyLF;
-                        continue;
-                    }
-                    buffer.push(ch);
-                }
-                LoadState::BeforeSetKeyLF => {
-                    if char_string != LF {
-                        return Err(Error::new(ErrorKind::InvalidSerializedString));
-                    }
+            }
+        }
 
-                    let key_len = String::from(&buffer[0..buffer.len()]).parse()?;
-                    state = LoadState::GetSetKey(key_len);
-                    buffer.clear();
-                    continue;
-                }
-                LoadState::GetSetKey(len) => {
-                    if buffer.len() < len - 1 {
-                        buffer.push(ch);
-                        continue;
-                    }
-                    if buffer.len() > len - 1 {
-                        unreachable!();
-                    }
+        let mut report = None;
+        if valid_len < len {
+            if !self.read_only {
+                self.file.set_len(valid_len)?;
+            }
+            report = Some(RecoveryReport {
+                              dropped_bytes: len - valid_len,
+                              dropped_records,
+                          });
+        }
+        self.txn_id = replayer.txn_id;
+        replayer.store.set_base_version(replayer.txn_id);
 
-                    buffer.push(ch);
-                    cache = buffer.clone();
-                    buffer.clear();
-                    state = LoadState::BeforeSetValCR;
-                }
-                LoadState::BeforeSetValCR => {
-                    if char_string == CR {
-                        state = LoadState::BeforeSetValLF;
-                        continue;
-                    }
-                    buffer.push(ch);
-                }
-                LoadState::BeforeSetValLF => {
-                    if char_string != LF {
-                        return Err(Error::new(ErrorKind::InvalidSerializedString));
-                    }
+        if self.format == Format::Text {
+            // It is never compacted automatically, since compacting it
+            // rewrites it in the binary format.
+            self.live_size = valid_len;
+        } else {
+            self.header_written = valid_len > 0;
+            self.live_size = live_size(&replayer.store) + self.log_base_size();
+        }
 
-                    let val_len = String::from(&buffer[0..buffer.len()]).parse()?;
-                    state = LoadState::GetSetVal(val_len);
-                    buffer.clear();
-                    continue;
+        Ok((replayer.store, report))
+    }
+
+    fn replay_text(&mut self, replayer: &mut Replayer, recover: bool) -> Result<Replayed> {
+        let mut bytes = Vec::new();
+        fs::File::open(&self.path)?.read_to_end(&mut bytes)?;
+
+        // Only the valid UTF-8 prefix could be parsed. The rest of the
+        // file is torn if it ends with an incomplete character,
+        // otherwise it is damaged.
+        let (text, damaged) = match String::from_utf8(bytes) {
+            Ok(text) => (text, false),
+            Err(err) => {
+                if err.utf8_error().error_len().is_some() && !recover {
+                    return Err(Error::from(err));
                 }
-                LoadState::GetSetVal(len) => {
-                    if buffer.len() < len - 1 {
-                        buffer.push(ch);
-                        continue;
-                    }
-                    if buffer.len() > len - 1 {
-                        unreachable!();
-                    }
+                let valid_len = err.utf8_error().valid_up_to();
+                let mut bytes = err.into_bytes();
+                bytes.truncate(valid_len);
+                (String::from_utf8(bytes)?, true)
+            }
+        };
 
-                    buffer.push(ch);
-                    btree.insert(cache.clone(), Data::try_from(buffer.clone())?);
-                    cache.clear();
-                    buffer.clear();
-                    state = LoadState::Empty;
+        // The parser is finished at the end, for the last record
+        // which has no checksum.
+        let mut parser = TextParser::new();
+        for ch in text.chars().map(Some).chain(std::iter::once(None)) {
+            let parsed = match ch {
+                Some(ch) => parser.feed(ch),
+                None => parser.finish(),
+            };
+            let result = parsed.and_then(|parsed| match parsed {
+                              Some((record, offset)) => replayer.replay(record, offset),
+                              None => Ok(()),
+                          });
+            if let Err(err) = result {
+                if !recover {
+                    return Err(err);
                 }
-                LoadState::BeforeRemoveKeyCR => {
-                    if char_string == CR {
-                        state = LoadState::BeforeRemoveKeyLF;
-                        continue;
-                    }
-                    buffer.push(ch);
+                return Ok(Replayed {
+                              torn: true,
+                              offset: parser.record_offset(),
+                          });
+            }
+        }
+
+        if damaged && !parser.is_pending() {
+            return Ok(Replayed {
+                          torn: true,
+                          offset: text.len() as u64,
+                      });
+        }
+        Ok(Replayed {
+               torn: parser.is_pending(),
+               offset: parser.record_offset(),
+           })
+    }
+
+    fn snapshot_path(&self) -> Option<String> {
+        self.manifest
+            .snapshot
+            .as_ref()
+            .map(|snapshot| resolve(&self.manifest_path(), snapshot))
+    }
+
+    fn manifest_path(&self) -> String {
+        format!("{}.manifest", self.path)
+    }
+
+    // Return the generation of the checkpoint which started the log.
+    fn log_generation(&self) -> Result<u64> {
+        if self.format == Format::Text || !self.header_written {
+            return Ok(0);
+        }
+
+        let mut reader = BufReader::new(fs::File::open(&self.path)?);
+        let mut header = Vec::new();
+        (&mut reader).take(HEADER_LEN).read_to_end(&mut header)?;
+        match Record::read(&mut reader) {
+            Ok(Parsed::Record(Record::Checkpoint(generation), _)) => Ok(generation),
+            _ => Ok(0),
+        }
+    }
+
+    // The size of the log right after a checkpoint.
+    fn log_base_size(&self) -> u64 {
+        if self.manifest.generation == 0 {
+            return 0;
+        }
+        HEADER_LEN + Record::Checkpoint(self.manifest.generation).encode().len() as u64
+    }
+
+    // The records to set the live data, followed by an empty
+    // transaction with the id of the last one, so the versions handed
+    // out before are still known once the file is rewritten.
+    fn live_records(&self, store: &Store) -> Vec<Record> {
+        let mut records = set_records(store);
+        if self.txn_id > 0 {
+            records.push(Record::Begin(self.txn_id));
+            records.push(Record::Commit(self.txn_id));
+        }
+        records
+    }
+
+    // Start a fresh log for the current generation.
+    fn reset_log(&mut self) -> Result<()> {
+        self.file.set_len(0)?;
+        self.header_written = false;
+        let generation = self.manifest.generation;
+        self.append(Record::Checkpoint(generation))
+    }
+
+    fn reopen(&mut self) -> Result<()> {
+        self.file = fs::OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(&self.path)?;
+        self.format = Format::Binary;
+        self.header_written = true;
+        Ok(())
+    }
+
+    fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(Error::new(ErrorKind::TransactionNotWritable));
+        }
+        Ok(())
+    }
+
+    // Append the record to the file, along with the header if this is
+    // the first record. A file in the text format is compacted first,
+    // since the records could only be appended in the binary format.
+    fn append(&mut self, record: Record) -> Result<()> {
+        self.check_writable()?;
+        if self.format == Format::Text {
+            let store = self.load()?;
+            self.compact(&store)?;
+        }
+
+        let mut bytes = Vec::new();
+        if !self.header_written {
+            bytes.extend_from_slice(MAGIC);
+            bytes.push(FORMAT_VERSION);
+        }
+        bytes.extend(record.encode());
+        self.file.write_all(&bytes)?;
+        self.header_written = true;
+        Ok(())
+    }
+}
+
+// Take the advisory lock of the store at the given path, which is on
+// the lock file next to it, since the file itself is replaced by the
+// compaction. The lock file is created by the writers. A reader never
+// creates it, and locks the file itself if it does not exist, which is
+// checked by the writers as well.
+fn lock(path: &str, shared: bool) -> Result<fs::File> {
+    let lock_path = format!("{}.lock", path);
+    let file = if shared {
+        match fs::File::open(&lock_path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => fs::File::open(path)?,
+            Err(err) => return Err(Error::from(err)),
+        }
+    } else {
+        fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&lock_path)?
+    };
+    try_lock(&file, shared)?;
+    Ok(file)
+}
+
+fn try_lock(file: &fs::File, shared: bool) -> Result<()> {
+    let result = if shared {
+        file.try_lock_shared()
+    } else {
+        file.try_lock()
+    };
+    match result {
+        Ok(()) => Ok(()),
+        Err(fs::TryLockError::WouldBlock) => Err(Error::new(ErrorKind::DatabaseLocked)),
+        Err(fs::TryLockError::Error(err)) => Err(Error::from(err)),
+    }
+}
+
+// Replay the records of a file in the binary format.
+fn replay_binary(path: &str, replayer: &mut Replayer, recover: bool) -> Result<Replayed> {
+    let mut reader = BufReader::new(fs::File::open(path)?);
+    let mut header = Vec::new();
+    (&mut reader).take(HEADER_LEN).read_to_end(&mut header)?;
+    if header.len() < HEADER_LEN as usize {
+        return Ok(Replayed {
+                      torn: !header.is_empty(),
+                      offset: 0,
+                  });
+    }
+
+    let mut offset = HEADER_LEN;
+    loop {
+        let result = Record::read(&mut reader).and_then(|parsed| match parsed {
+            Parsed::Record(record, len) => {
+                replayer.replay(record, offset)?;
+                offset += len;
+                Ok(Some(false))
+            }
+            Parsed::End => Ok(None),
+            Parsed::Torn => Ok(Some(true)),
+        });
+
+        let torn = match result {
+            Ok(Some(false)) => continue,
+            Ok(Some(true)) => true,
+            Ok(None) => false,
+            Err(err) => {
+                if !recover {
+                    return Err(err);
                 }
-                LoadState::BeforeRemoveKeyLF => {
-                    if char_string != LF {
-                        return Err(Error::new(ErrorKind::InvalidSerializedString));
-                    }
+                true
+            }
+        };
+        return Ok(Replayed {
+                      torn,
+                      offset,
+                  });
+    }
+}
+
+// Write the records to a new file in the binary format, and sync it
+// to the disk.
+fn write_file<I>(path: &str, records: I) -> Result<()>
+    where I: IntoIterator<Item = Record>
+{
+    let mut writer = BufWriter::new(fs::File::create(path)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[FORMAT_VERSION])?;
+    for record in records {
+        writer.write_all(&record.encode())?;
+    }
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    Ok(())
+}
 
-                    let key_len = String::from(&buffer[0..buffer.len()]).parse()?;
-                    state = LoadState::GetRemoveKey(key_len);
-                    buffer.clear();
-                    continue;
+// The records to set the live data, followed by the deadlines of the
+// keys which expire later. The expired keys are dropped.
+fn set_records(store: &Store) -> Vec<Record> {
+    let mut records = Vec::new();
+    let mut deadlines = Vec::new();
+    for (key, data) in Iter::all(store) {
+        records.push(Record::Set(key.clone(), data.clone()));
+        if let Some(&deadline) = store.deadlines().get(key) {
+            deadlines.push(Record::Expire(key.clone(), deadline));
+        }
+    }
+    records.extend(deadlines);
+    records
+}
+
+// The size of the file which only has the live data.
+fn live_size(store: &Store) -> u64 {
+    set_records(store)
+        .into_iter()
+        .map(|record| record.encode().len() as u64)
+        .sum::<u64>() + HEADER_LEN
+}
+
+impl Replayer {
+    fn new() -> Replayer {
+        Replayer {
+            store: Store::default(),
+            batch: None,
+            txn_id: 0,
+        }
+    }
+
+    // Replay a record which begins at the offset of the file.
+    fn replay(&mut self, record: Record, offset: u64) -> Result<()> {
+        match record {
+            Record::Begin(id) => {
+                // The previous transaction is torn if it is not
+                // committed before the next one begins.
+                self.batch = Some(Batch {
+                                      id,
+                                      offset,
+                                      records: Vec::new(),
+                                  });
+                if id > self.txn_id {
+                    self.txn_id = id;
                 }
-                LoadState::GetRemoveKey(len) => {
-                    if buffer.len() < len - 1 {
-                        buffer.push(ch);
-                        continue;
-                    }
-                    if buffer.len() > len - 1 {
-                        unreachable!();
+            }
+            // The log segment is already checked against the manifest.
+            Record::Checkpoint(_) => {}
+            Record::Commit(id) => {
+                match self.batch.take() {
+                    Some(batch) => {
+                        if batch.id != id {
+                            return Err(Error::new(ErrorKind::InvalidSerializedString));
+                        }
+                        for record in batch.records {
+                            record.apply(&mut self.store)?;
+                        }
                     }
-
-                    buffer.push(ch);
-                    btree.remove(&buffer);
-                    buffer.clear();
-                    state = LoadState::Empty;
+                    None => return Err(Error::new(ErrorKind::InvalidSerializedString)),
+                }
+            }
+            record => {
+                match self.batch {
+                    Some(ref mut batch) => batch.records.push(record),
+                    // Records written without transaction marks.
+                    None => record.apply(&mut self.store)?,
                 }
             }
         }
 
-        if state != LoadState::Empty {
-            return Err(Error::new(ErrorKind::InvalidSerializedString));
-        }
+        Ok(())
+    }
+}
 
-        Ok(btree)
+impl Persistable for FileStore {
+    fn set(&mut self, key: String, data: Data) -> Result<()> {
+        self.append(Record::Set(key, data))
     }
 
+    fn remove(&mut self, key: String) -> Result<()> {
+        self.append(Record::Remove(key))
+    }
+
+    fn remove_all(&mut self) -> Result<()> {
+        self.append(Record::Clear)
+    }
+
+    fn expire(&mut self, key: String, deadline: u64) -> Result<()> {
+        self.append(Record::Expire(key, deadline))
+    }
+
+    fn persist(&mut self, key: String) -> Result<()> {
+        self.append(Record::Persist(key))
+    }
+
+    fn apply(&mut self, key: String, op: Op) -> Result<()> {
+        self.append(Record::Apply(key, op))
+    }
+
+    fn begin(&mut self, id: u64) -> Result<()> {
+        self.append(Record::Begin(id))?;
+        self.txn_id = id;
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        let id = self.txn_id;
+        self.append(Record::Commit(id))
+    }
+
+    fn load(&mut self) -> Result<Store> {
+        Ok(self.replay(false)?.0)
+    }
+
+    // Drop the log and the snapshot. The snapshot is dropped from the
+    // manifest first, so it is never loaded with a partial log. Once
+    // checkpointed, the fresh log is marked with the new generation,
+    // otherwise it is taken as stale on load.
     fn clear(&mut self) -> Result<()> {
-        Ok(self.file.set_len(0)?)
+        self.check_writable()?;
+        let snapshot = self.snapshot_path();
+        self.format = Format::Binary;
+        if self.manifest.generation > 0 {
+            self.manifest.generation += 1;
+            self.manifest.snapshot = None;
+            self.manifest.write(&self.manifest_path())?;
+            self.reset_log()?;
+        } else {
+            self.file.set_len(0)?;
+            self.header_written = false;
+        }
+        self.snapshot_size = 0;
+        if let Some(snapshot) = snapshot {
+            let _ = fs::remove_file(snapshot);
+        }
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        Ok(self.file.sync_all()?)
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.file.unlock()?;
+        Ok(self.lock.unlock()?)
+    }
+
+    // Write the live data to a temporary file, then atomically rename
+    // it over the current file. Once checkpointed, the snapshot is
+    // rewritten instead.
+    fn compact(&mut self, store: &Store) -> Result<()> {
+        self.check_writable()?;
+        if self.manifest.generation > 0 {
+            return self.checkpoint(store);
+        }
+
+        let compact_path = format!("{}.compact", self.path);
+        write_file(&compact_path, self.live_records(store))?;
+        fs::rename(&compact_path, &self.path)?;
+        sync_dir(&self.path);
+
+        self.reopen()?;
+        self.live_size = self.file.metadata()?.len();
+        Ok(())
+    }
+
+    // Write the snapshot and the fresh log of the next generation,
+    // then switch to them by writing the manifest. A log which is not
+    // renamed over the current one before a crash is detected on load
+    // by its checkpoint mark.
+    fn checkpoint(&mut self, store: &Store) -> Result<()> {
+        self.check_writable()?;
+        let generation = self.manifest.generation + 1;
+        let snapshot_path = format!("{}.snapshot.{}", self.path, generation);
+        let segment_path = format!("{}.segment", self.path);
+        write_file(&snapshot_path, self.live_records(store))?;
+        write_file(&segment_path, vec![Record::Checkpoint(generation)])?;
+
+        let old_snapshot = self.snapshot_path();
+        let manifest = Manifest {
+            generation,
+            snapshot: Some(file_name(&snapshot_path)),
+            segments: vec![file_name(&self.path)],
+        };
+        manifest.write(&self.manifest_path())?;
+        fs::rename(&segment_path, &self.path)?;
+        sync_dir(&self.path);
+        if let Some(old_snapshot) = old_snapshot {
+            let _ = fs::remove_file(old_snapshot);
+        }
+
+        self.reopen()?;
+        self.manifest = manifest;
+        self.snapshot_size = fs::metadata(&snapshot_path)?.len();
+        self.live_size = self.size()?;
+        Ok(())
+    }
+
+    fn size(&self) -> Result<u64> {
+        Ok(self.snapshot_size + self.file.metadata()?.len())
+    }
+
+    fn live_size(&self) -> u64 {
+        self.live_size
+    }
+
+    fn log_size(&self) -> Result<u64> {
+        Ok(self.file.metadata()?.len())
     }
 }
 
@@ -234,13 +811,65 @@ impl Persistable for MemoryStore {
         Ok(())
     }
 
-    fn load(&mut self) -> Result<BTreeMap<String, Data>> {
-        Ok(BTreeMap::new())
+    fn remove_all(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn expire(&mut self, _: String, _: u64) -> Result<()> {
+        Ok(())
+    }
+
+    fn persist(&mut self, _: String) -> Result<()> {
+        Ok(())
+    }
+
+    fn apply(&mut self, _: String, _: Op) -> Result<()> {
+        Ok(())
+    }
+
+    fn begin(&mut self, _: u64) -> Result<()> {
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn load(&mut self) -> Result<Store> {
+        Ok(Store::default())
     }
 
     fn clear(&mut self) -> Result<()> {
         Ok(())
     }
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn compact(&mut self, _: &Store) -> Result<()> {
+        Ok(())
+    }
+
+    fn checkpoint(&mut self, _: &Store) -> Result<()> {
+        Ok(())
+    }
+
+    fn size(&self) -> Result<u64> {
+        Ok(0)
+    }
+
+    fn live_size(&self) -> u64 {
+        0
+    }
+
+    fn log_size(&self) -> Result<u64> {
+        Ok(0)
+    }
 }
 
 #[cfg(test)]
@@ -259,6 +888,12 @@ mod memory_store_tests {
         assert!(store.remove(String::from("test")).is_ok());
     }
 
+    #[test]
+    fn test_remove_all() {
+        let mut store = MemoryStore::default();
+        assert!(store.remove_all().is_ok());
+    }
+
     #[test]
     fn test_load() {
         assert!(MemoryStore::default().load().unwrap().is_empty());
@@ -269,13 +904,27 @@ mod memory_store_tests {
         let mut store = MemoryStore::default();
         assert!(store.clear().is_ok());
     }
+
+    #[test]
+    fn test_sync() {
+        let mut store = MemoryStore::default();
+        assert!(store.sync().is_ok());
+    }
+
+    #[test]
+    fn test_compact() {
+        let mut store = MemoryStore::default();
+        assert!(store.compact(&Store::default()).is_ok());
+        assert_eq!(0, store.size().unwrap());
+    }
 }
 
 #[cfg(test)]
 mod file_store_tests {
     use super::*;
     use std::env;
-    use std::io::Read;
+    use checksum::crc32;
+    use op::Side;
 
     fn get_cdb_path(name: &str) -> String {
         let mut cdb_path = env::current_dir().unwrap();
@@ -283,16 +932,60 @@ mod file_store_tests {
         String::from(cdb_path.as_path().to_str().unwrap())
     }
 
+    // Remove the file of the store, along with its lock and manifest.
+    fn remove_cdb(path: &str) {
+        for suffix in &["", ".lock", ".manifest"] {
+            let _ = fs::remove_file(format!("{}{}", path, suffix));
+        }
+    }
+
+    fn encode(records: Vec<Record>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for record in records {
+            bytes.extend(record.encode());
+        }
+        bytes
+    }
+
+    fn with_header(records: Vec<Record>) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(FORMAT_VERSION);
+        bytes.extend(encode(records));
+        bytes
+    }
+
+    fn with_checksum(records: &[&str]) -> String {
+        records
+            .iter()
+            .map(|record| format!("{}@{}\r\n", record, crc32(record.as_bytes())))
+            .collect()
+    }
+
+    fn set(key: &str, data: Data) -> Record {
+        Record::Set(String::from(key), data)
+    }
+
+    fn read_file(path: &str) -> Vec<u8> {
+        let mut content = Vec::new();
+        fs::File::open(path)
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        content
+    }
+
     #[test]
     fn test_new() {
-        let mut store = FileStore::new(get_cdb_path("test_new.cdb")).unwrap();
+        let mut store = FileStore::new(get_cdb_path("test_new.cdb"), OpenMode::Truncate).unwrap();
         store.clear().unwrap();
         assert!(store.file.metadata().unwrap().is_file());
+        remove_cdb(&store.path);
     }
 
     #[test]
     fn test_set() {
-        let mut store = FileStore::new(get_cdb_path("test_set.cdb")).unwrap();
+        let path = get_cdb_path("test_set.cdb");
+        let mut store = FileStore::new(path.clone(), OpenMode::Truncate).unwrap();
         store.clear().unwrap();
 
         store
@@ -302,63 +995,699 @@ mod file_store_tests {
             .set(String::from("key"), Data::String(String::from("value")))
             .unwrap();
 
-        let mut content = String::new();
-        fs::File::open(get_cdb_path("test_set.cdb"))
-            .unwrap()
-            .read_to_string(&mut content)
-            .unwrap();
-        assert_eq!("$3\r\nkey8\r\n+value\r\n$3\r\nkey8\r\n+value\r\n", content);
+        assert_eq!(with_header(vec![set("key", Data::String(String::from("value"))),
+                                    set("key", Data::String(String::from("value")))]),
+                   read_file(&path));
         store.clear().unwrap();
+        remove_cdb(&path);
     }
 
     #[test]
     fn test_clear() {
-        let mut store = FileStore::new(get_cdb_path("test_clear.cdb")).unwrap();
+        let path = get_cdb_path("test_clear.cdb");
+        let mut store = FileStore::new(path.clone(), OpenMode::Truncate).unwrap();
         store
             .set(String::from("key"), Data::String(String::from("value")))
             .unwrap();
+        assert!(!read_file(&path).is_empty());
 
-        let mut content = String::new();
-        fs::File::open(get_cdb_path("test_clear.cdb"))
-            .unwrap()
-            .read_to_string(&mut content)
-            .unwrap();
-        assert!(content.len() != 0);
+        store.clear().unwrap();
+        assert!(read_file(&path).is_empty());
 
+        store.set(String::from("key"), Data::Int(1)).unwrap();
+        assert_eq!(with_header(vec![set("key", Data::Int(1))]), read_file(&path));
         store.clear().unwrap();
+        remove_cdb(&path);
+    }
 
-        let mut content_after_clear = String::new();
-        fs::File::open(get_cdb_path("test_clear.cdb"))
-            .unwrap()
-            .read_to_string(&mut content_after_clear)
-            .unwrap();
-        assert!(content_after_clear.len() == 0);
+    #[test]
+    fn test_sync() {
+        let mut store = FileStore::new(get_cdb_path("test_sync.cdb"), OpenMode::Truncate).unwrap();
+        store.set(String::from("key"), Data::Int(1)).unwrap();
+        assert!(store.sync().is_ok());
+        store.clear().unwrap();
+        remove_cdb(&store.path);
     }
 
     #[test]
     fn test_remove() {
-        let mut store = FileStore::new(get_cdb_path("test_remove.cdb")).unwrap();
+        let path = get_cdb_path("test_remove.cdb");
+        let mut store = FileStore::new(path.clone(), OpenMode::Truncate).unwrap();
         store.clear().unwrap();
 
         store.remove(String::from("key1")).unwrap();
         store.remove(String::from("key2")).unwrap();
 
-        let mut content = String::new();
-        fs::File::open(get_cdb_path("test_remove.cdb"))
-            .unwrap()
-            .read_to_string(&mut content)
-            .unwrap();
-        assert_eq!("#4\r\nkey1#4\r\nkey2", content);
+        assert_eq!(with_header(vec![Record::Remove(String::from("key1")),
+                                    Record::Remove(String::from("key2"))]),
+                   read_file(&path));
         store.clear().unwrap();
+        remove_cdb(&path);
+    }
+
+    #[test]
+    fn test_remove_all() {
+        let path = get_cdb_path("test_remove_all.cdb");
+        let mut store = FileStore::new(path.clone(), OpenMode::Truncate).unwrap();
+        store.set(String::from("key1"), Data::Int(1)).unwrap();
+        store.remove_all().unwrap();
+        store.set(String::from("key2"), Data::Int(2)).unwrap();
+
+        assert_eq!(with_header(vec![set("key1", Data::Int(1)),
+                                    Record::Clear,
+                                    set("key2", Data::Int(2))]),
+                   read_file(&path));
+
+        let tree = store.load().unwrap();
+        assert_eq!(1, tree.len());
+        assert_eq!(Some(&Data::Int(2)), tree.get("key2"));
+        store.clear().unwrap();
+        remove_cdb(&path);
     }
 
     #[test]
     fn test_load() {
-        let mut store = FileStore::new(get_cdb_path("test_load.cdb")).unwrap();
-        write!(store.file, "$3\r\nkey8\r\n+value\r\n").unwrap();
+        let mut store = FileStore::new(get_cdb_path("test_load.cdb"), OpenMode::Truncate).unwrap();
+        store
+            .file
+            .write_all(&with_header(vec![set("key", Data::String(String::from("value")))]))
+            .unwrap();
+
+        let tree = store.load().unwrap();
+        assert_eq!(1, tree.len());
+        store.clear().unwrap();
+        remove_cdb(&store.path);
+    }
+
+    #[test]
+    fn test_load_expire() {
+        let path = get_cdb_path("test_load_expire.cdb");
+        let mut store = FileStore::new(path.clone(), OpenMode::Truncate).unwrap();
+        store.set(String::from("key1"), Data::Int(1)).unwrap();
+        store.set(String::from("key2"), Data::Int(2)).unwrap();
+        store.set(String::from("key3"), Data::Int(3)).unwrap();
+        store.expire(String::from("key1"), 1).unwrap();
+        store.expire(String::from("key2"), u64::MAX).unwrap();
+        store.expire(String::from("key3"), 1).unwrap();
+        store.persist(String::from("key3")).unwrap();
+        assert_eq!(with_header(vec![set("key1", Data::Int(1)),
+                                    set("key2", Data::Int(2)),
+                                    set("key3", Data::Int(3)),
+                                    Record::Expire(String::from("key1"), 1),
+                                    Record::Expire(String::from("key2"), u64::MAX),
+                                    Record::Expire(String::from("key3"), 1),
+                                    Record::Persist(String::from("key3"))]),
+                   read_file(&path));
+
+        let tree = store.load().unwrap();
+        assert_eq!(2, tree.len());
+        assert!(tree.get("key1").is_none());
+        assert!(tree.ttl("key2").is_some());
+        assert!(tree.ttl("key3").is_none());
+
+        // The expired key is dropped by the compaction.
+        store.compact(&tree).unwrap();
+        assert_eq!(with_header(vec![set("key2", Data::Int(2)),
+                                    set("key3", Data::Int(3)),
+                                    Record::Expire(String::from("key2"), u64::MAX)]),
+                   read_file(&path));
+        assert_eq!(store.size().unwrap(), store.live_size());
+        store.clear().unwrap();
+        remove_cdb(&path);
+    }
+
+    #[test]
+    fn test_load_apply() {
+        let path = get_cdb_path("test_load_apply.cdb");
+        let mut store = FileStore::new(path.clone(), OpenMode::Truncate).unwrap();
+        store.apply(String::from("key"), Op::Push(Side::Right, Data::Int(1))).unwrap();
+        store.apply(String::from("key"), Op::Push(Side::Left, Data::Int(2))).unwrap();
+        store.apply(String::from("key"), Op::Pop(Side::Right)).unwrap();
+        assert_eq!(with_header(vec![Record::Apply(String::from("key"),
+                                                  Op::Push(Side::Right, Data::Int(1))),
+                                    Record::Apply(String::from("key"),
+                                                  Op::Push(Side::Left, Data::Int(2))),
+                                    Record::Apply(String::from("key"), Op::Pop(Side::Right))]),
+                   read_file(&path));
+
+        let tree = store.load().unwrap();
+        assert_eq!(Some(&Data::List(vec![Data::Int(2)].into_iter().collect())),
+                   tree.get("key"));
+
+        // An operation against the wrong type of data is damage.
+        store.set(String::from("key"), Data::Int(1)).unwrap();
+        store.apply(String::from("key"), Op::Pop(Side::Left)).unwrap();
+        assert!(store.load().is_err());
+        store.clear().unwrap();
+        remove_cdb(&path);
+    }
+
+    #[test]
+    fn test_load_non_ascii() {
+        let path = get_cdb_path("test_load_non_ascii.cdb");
+        let mut store = FileStore::new(path.clone(), OpenMode::Truncate).unwrap();
+        store
+            .set(String::from("键"), Data::String(String::from("值")))
+            .unwrap();
+        store.set(String::from("ключ"), Data::Int(1)).unwrap();
+        store.remove(String::from("ключ")).unwrap();
+        drop(store);
+
+        let mut store = FileStore::new(path.clone(), OpenMode::OpenExisting).unwrap();
+        let tree = store.load().unwrap();
+        assert_eq!(1, tree.len());
+        assert_eq!(Some(&Data::String(String::from("值"))), tree.get("键"));
+        store.clear().unwrap();
+        remove_cdb(&path);
+    }
+
+    #[test]
+    fn test_begin_commit() {
+        let path = get_cdb_path("test_begin_commit.cdb");
+        let mut store = FileStore::new(path.clone(), OpenMode::Truncate).unwrap();
+        store.begin(1).unwrap();
+        store.set(String::from("key"), Data::Int(1)).unwrap();
+        store.commit().unwrap();
+        store.begin(2).unwrap();
+        store.remove(String::from("key")).unwrap();
+        store.commit().unwrap();
+
+        assert_eq!(with_header(vec![Record::Begin(1),
+                                    set("key", Data::Int(1)),
+                                    Record::Commit(1),
+                                    Record::Begin(2),
+                                    Record::Remove(String::from("key")),
+                                    Record::Commit(2)]),
+                   read_file(&path));
+        assert!(store.load().unwrap().is_empty());
+        store.clear().unwrap();
+        remove_cdb(&path);
+    }
+
+    #[test]
+    fn test_load_committed() {
+        let mut store = FileStore::new(get_cdb_path("test_load_committed.cdb"),
+                                       OpenMode::Truncate)
+                .unwrap();
+        store
+            .file
+            .write_all(&with_header(vec![set("a", Data::Int(1)),
+                                         Record::Begin(7),
+                                         set("b", Data::Int(2)),
+                                         Record::Remove(String::from("a")),
+                                         Record::Commit(7)]))
+            .unwrap();
+
+        let tree = store.load().unwrap();
+        assert_eq!(1, tree.len());
+        assert_eq!(Some(&Data::Int(2)), tree.get("b"));
+        assert_eq!(7, store.txn_id);
+        store.clear().unwrap();
+        remove_cdb(&store.path);
+    }
+
+    #[test]
+    fn test_load_torn() {
+        let mut store = FileStore::new(get_cdb_path("test_load_torn.cdb"), OpenMode::Truncate)
+            .unwrap();
+        let committed = with_header(vec![Record::Begin(1), set("a", Data::Int(1)), Record::Commit(1)]);
+        let torn = set("c", Data::Int(3)).encode();
+        store.file.write_all(&committed).unwrap();
+        store
+            .file
+            .write_all(&encode(vec![Record::Begin(2), set("b", Data::Int(2))]))
+            .unwrap();
+        store.file.write_all(&torn[..torn.len() - 1]).unwrap();
+
+        let tree = store.load().unwrap();
+        assert_eq!(1, tree.len());
+        assert_eq!(Some(&Data::Int(1)), tree.get("a"));
+        assert_eq!(committed.len() as u64, store.file.metadata().unwrap().len());
+
+        store.begin(3).unwrap();
+        store.set(String::from("d"), Data::Int(4)).unwrap();
+        store.commit().unwrap();
+        let tree = store.load().unwrap();
+        assert_eq!(2, tree.len());
+        assert_eq!(Some(&Data::Int(4)), tree.get("d"));
+        store.clear().unwrap();
+        remove_cdb(&store.path);
+    }
+
+    #[test]
+    fn test_load_torn_header() {
+        let path = get_cdb_path("test_load_torn_header.cdb");
+        fs::write(&path, &MAGIC[..2]).unwrap();
+        let mut store = FileStore::new(path.clone(), OpenMode::OpenExisting).unwrap();
+        assert!(store.load().is_err());
+
+        let (tree, report) = store.recover().unwrap();
+        assert!(tree.is_empty());
+        assert_eq!(Some(RecoveryReport {
+                            dropped_bytes: 2,
+                            dropped_records: 1,
+                        }),
+                   report);
+
+        store.set(String::from("key"), Data::Int(1)).unwrap();
+        assert_eq!(with_header(vec![set("key", Data::Int(1))]), read_file(&path));
+        store.clear().unwrap();
+        remove_cdb(&path);
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let path = get_cdb_path("test_unsupported_version.cdb");
+        let mut header = MAGIC.to_vec();
+        header.push(FORMAT_VERSION + 1);
+        fs::write(&path, &header).unwrap();
+        match FileStore::new(path.clone(), OpenMode::OpenExisting) {
+            Err(Error { kind: ErrorKind::UnsupportedFormatVersion }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        remove_cdb(&path);
+    }
+
+    #[test]
+    fn test_load_mismatched_commit() {
+        let mut store = FileStore::new(get_cdb_path("test_load_mismatched_commit.cdb"),
+                                       OpenMode::Truncate)
+                .unwrap();
+        store
+            .file
+            .write_all(&with_header(vec![Record::Begin(1),
+                                         set("a", Data::Int(1)),
+                                         Record::Commit(2)]))
+            .unwrap();
+        assert!(store.load().is_err());
+        store.clear().unwrap();
+        remove_cdb(&store.path);
+    }
+
+    #[test]
+    fn test_load_checksum_mismatch() {
+        let mut store = FileStore::new(get_cdb_path("test_load_checksum_mismatch.cdb"),
+                                       OpenMode::Truncate)
+                .unwrap();
+        let mut bytes = with_header(vec![set("a", Data::Int(1))]);
+        let len = bytes.len();
+        bytes[len - 7] = b'2';
+        store.file.write_all(&bytes).unwrap();
+        match store.load() {
+            Err(Error { kind: ErrorKind::ChecksumMismatch }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        store.clear().unwrap();
+        remove_cdb(&store.path);
+    }
+
+    #[test]
+    fn test_recover() {
+        let mut store = FileStore::new(get_cdb_path("test_recover.cdb"), OpenMode::Truncate)
+            .unwrap();
+        let valid = with_header(vec![Record::Begin(1),
+                                     set("a", Data::Int(1)),
+                                     Record::Commit(1),
+                                     set("b", Data::Int(2))]);
+        let mut damaged = encode(vec![Record::Begin(2), set("c", Data::Int(3)), Record::Commit(2)]);
+        damaged[30] = b'4';
+        store.file.write_all(&valid).unwrap();
+        store.file.write_all(&damaged).unwrap();
+        assert!(store.load().is_err());
+
+        let (tree, report) = store.recover().unwrap();
+        assert_eq!(2, tree.len());
+        assert_eq!(Some(&Data::Int(2)), tree.get("b"));
+        assert_eq!(Some(RecoveryReport {
+                            dropped_bytes: damaged.len() as u64,
+                            dropped_records: 2,
+                        }),
+                   report);
+        assert_eq!(valid.len() as u64, store.file.metadata().unwrap().len());
+
+        store.begin(2).unwrap();
+        store.set(String::from("c"), Data::Int(3)).unwrap();
+        store.commit().unwrap();
+        let (tree, report) = store.recover().unwrap();
+        assert_eq!(3, tree.len());
+        assert!(report.is_none());
+        store.clear().unwrap();
+        remove_cdb(&store.path);
+    }
+
+    #[test]
+    fn test_recover_garbage() {
+        let mut store = FileStore::new(get_cdb_path("test_recover_garbage.cdb"),
+                                       OpenMode::Truncate)
+                .unwrap();
+        store
+            .file
+            .write_all(&with_header(vec![set("a", Data::Int(1))]))
+            .unwrap();
+        store.file.write_all(b"garbage").unwrap();
+        assert!(store.load().is_err());
+
+        let (tree, report) = store.recover().unwrap();
+        assert_eq!(1, tree.len());
+        assert_eq!(Some(RecoveryReport {
+                            dropped_bytes: 7,
+                            dropped_records: 1,
+                        }),
+                   report);
+        store.clear().unwrap();
+        remove_cdb(&store.path);
+    }
+
+    #[test]
+    fn test_load_text() {
+        let path = get_cdb_path("test_load_text.cdb");
+        fs::write(&path,
+                  with_checksum(&["$3\r\n键4\r\n:1\r\n", "[1\r\n", "$1\r\nb9\r\n+值值\r\n",
+                                  "#3\r\n键", "]1\r\n"]))
+                .unwrap();
+
+        // The file is only rewritten in the binary format once
+        // compacted.
+        let text = read_file(&path);
+        let mut store = FileStore::new(path.clone(), OpenMode::OpenExisting).unwrap();
+        let tree = store.load().unwrap();
+        assert_eq!(1, tree.len());
+        assert_eq!(Some(&Data::String(String::from("值值"))), tree.get("b"));
+        assert_eq!(1, store.txn_id);
+        assert_eq!(1, tree.base_version());
+        assert_eq!(text, read_file(&path));
+        assert_eq!(store.size().unwrap(), store.live_size());
+
+        store.compact(&tree).unwrap();
+        assert_eq!(with_header(vec![set("b", Data::String(String::from("值值"))),
+                                    Record::Begin(1),
+                                    Record::Commit(1)]),
+                   read_file(&path));
+
+        store.set(String::from("c"), Data::Int(3)).unwrap();
+        assert_eq!(2, store.load().unwrap().len());
+        store.clear().unwrap();
+        remove_cdb(&path);
+    }
+
+    #[test]
+    fn test_load_text_torn() {
+        let path = get_cdb_path("test_load_text_torn.cdb");
+        let committed = with_checksum(&["$1\r\na4\r\n:1\r\n"]);
+        fs::write(&path,
+                  format!("{}{}$1\r\nc4\r\n:3",
+                          committed,
+                          with_checksum(&["[2\r\n", "$1\r\nb4\r\n:2\r\n"])))
+                .unwrap();
+
+        let mut store = FileStore::new(path.clone(), OpenMode::OpenExisting).unwrap();
+        let (tree, report) = store.recover().unwrap();
+        assert_eq!(1, tree.len());
+        assert_eq!(Some(&Data::Int(1)), tree.get("a"));
+        assert_eq!(3, report.unwrap().dropped_records);
+        assert_eq!(committed.as_bytes(), &read_file(&path)[..]);
+
+        store.compact(&tree).unwrap();
+        assert_eq!(with_header(vec![set("a", Data::Int(1)), Record::Begin(2), Record::Commit(2)]),
+                   read_file(&path));
+        store.clear().unwrap();
+        remove_cdb(&path);
+    }
+
+    #[test]
+    fn test_load_text_without_checksum() {
+        let path = get_cdb_path("test_load_text_without_checksum.cdb");
+        let text = "$3\r\nkey8\r\n+value\r\n[1\r\n$1\r\na4\r\n:1\r\n]1\r\n";
+        for &recover in &[false, true] {
+            fs::write(&path, text).unwrap();
+            let mut store = FileStore::new(path.clone(), OpenMode::OpenExisting).unwrap();
+            let tree = if recover {
+                let (tree, report) = store.recover().unwrap();
+                assert_eq!(None, report);
+                tree
+            } else {
+                store.load().unwrap()
+            };
+            assert_eq!(2, tree.len());
+            assert_eq!(Some(&Data::String(String::from("value"))), tree.get("key"));
+            assert_eq!(Some(&Data::Int(1)), tree.get("a"));
+            assert_eq!(2, store.load().unwrap().len());
+            store.clear().unwrap();
+        }
+        remove_cdb(&path);
+    }
+
+    #[test]
+    fn test_append_text() {
+        let path = get_cdb_path("test_append_text.cdb");
+        fs::write(&path, with_checksum(&["$1\r\na4\r\n:1\r\n"])).unwrap();
+
+        // The file is rewritten before the record is appended.
+        let mut store = FileStore::new(path.clone(), OpenMode::OpenExisting).unwrap();
+        store.begin(1).unwrap();
+        store.set(String::from("b"), Data::Int(2)).unwrap();
+        store.commit().unwrap();
+        assert_eq!(with_header(vec![set("a", Data::Int(1)),
+                                    Record::Begin(1),
+                                    set("b", Data::Int(2)),
+                                    Record::Commit(1)]),
+                   read_file(&path));
+        assert_eq!(2, store.load().unwrap().len());
+        store.clear().unwrap();
+        remove_cdb(&path);
+    }
+
+    #[test]
+    fn test_compact() {
+        let path = get_cdb_path("test_compact.cdb");
+        let mut store = FileStore::new(path.clone(), OpenMode::Truncate).unwrap();
+        for i in 0..100 {
+            store.set(String::from("key1"), Data::Int(i)).unwrap();
+            store.set(String::from("key2"), Data::Int(i)).unwrap();
+        }
+        store.remove(String::from("key2")).unwrap();
+        let tree = store.load().unwrap();
+        let size = store.size().unwrap();
+        assert_eq!(with_header(vec![set("key1", Data::Int(99))]).len() as u64,
+                   store.live_size());
+
+        store.compact(&tree).unwrap();
+        assert!(store.size().unwrap() < size);
+        assert_eq!(store.size().unwrap(), store.live_size());
+        assert!(fs::metadata(format!("{}.compact", path)).is_err());
+        assert_eq!(tree, store.load().unwrap());
+
+        store.set(String::from("key3"), Data::Int(3)).unwrap();
+        let tree = store.load().unwrap();
+        assert_eq!(2, tree.len());
+        assert_eq!(Some(&Data::Int(3)), tree.get("key3"));
+        store.clear().unwrap();
+        remove_cdb(&path);
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let path = get_cdb_path("test_checkpoint.cdb");
+        let mut store = FileStore::new(path.clone(), OpenMode::Truncate).unwrap();
+        for i in 0..10 {
+            store.set(String::from("key1"), Data::Int(i)).unwrap();
+        }
+        store.set(String::from("key2"), Data::Int(2)).unwrap();
+        let tree = store.load().unwrap();
+
+        store.checkpoint(&tree).unwrap();
+        assert_eq!(with_header(vec![Record::Checkpoint(1)]), read_file(&path));
+        assert_eq!(with_header(vec![set("key1", Data::Int(9)), set("key2", Data::Int(2))]),
+                   read_file(&format!("{}.snapshot.1", path)));
+        assert_eq!(store.size().unwrap(), store.live_size());
 
+        store.remove(String::from("key2")).unwrap();
+        drop(store);
+        let mut store = FileStore::new(path.clone(), OpenMode::OpenExisting).unwrap();
         let tree = store.load().unwrap();
         assert_eq!(1, tree.len());
+        assert_eq!(Some(&Data::Int(9)), tree.get("key1"));
+
+        store.checkpoint(&tree).unwrap();
+        assert!(fs::metadata(format!("{}.snapshot.1", path)).is_err());
+        assert_eq!(tree, store.load().unwrap());
+        assert_eq!(with_header(vec![Record::Checkpoint(2)]), read_file(&path));
+
+        store.clear().unwrap();
+        assert!(store.load().unwrap().is_empty());
+        assert!(fs::metadata(format!("{}.snapshot.2", path)).is_err());
+        drop(store);
+        FileStore::new(path.clone(), OpenMode::Truncate).unwrap();
+        assert!(fs::metadata(format!("{}.manifest", path)).is_err());
+        remove_cdb(&path);
+    }
+
+    #[test]
+    fn test_clear_after_checkpoint() {
+        let path = get_cdb_path("test_clear_after_checkpoint.cdb");
+        let mut store = FileStore::new(path.clone(), OpenMode::Truncate).unwrap();
+        store.set(String::from("a"), Data::Int(1)).unwrap();
+        let tree = store.load().unwrap();
+        store.checkpoint(&tree).unwrap();
+
+        // The fresh log is not taken as stale on the next load.
+        store.clear().unwrap();
+        assert_eq!(with_header(vec![Record::Checkpoint(2)]), read_file(&path));
+        store.set(String::from("b"), Data::Int(2)).unwrap();
+        drop(store);
+        let mut store = FileStore::new(path.clone(), OpenMode::OpenExisting).unwrap();
+        let tree = store.load().unwrap();
+        assert_eq!(1, tree.len());
+        assert_eq!(Some(&Data::Int(2)), tree.get("b"));
+        drop(store);
+
+        FileStore::new(path.clone(), OpenMode::Truncate).unwrap();
+        remove_cdb(&path);
+    }
+
+    #[test]
+    fn test_checkpoint_stale_log() {
+        let path = get_cdb_path("test_checkpoint_stale_log.cdb");
+        let mut store = FileStore::new(path.clone(), OpenMode::Truncate).unwrap();
+        store.set(String::from("key"), Data::Int(1)).unwrap();
+        let log = read_file(&path);
+        let tree = store.load().unwrap();
+        store.checkpoint(&tree).unwrap();
+        drop(store);
+
+        // As if the checkpoint was interrupted right after the manifest
+        // is written, before the fresh log is renamed.
+        fs::write(&path, log).unwrap();
+        let mut store = FileStore::new(path.clone(), OpenMode::OpenExisting).unwrap();
+        assert_eq!(tree, store.load().unwrap());
+        assert_eq!(with_header(vec![Record::Checkpoint(1)]), read_file(&path));
+        drop(store);
+
+        FileStore::new(path.clone(), OpenMode::Truncate).unwrap();
+        remove_cdb(&path);
+    }
+
+    #[test]
+    fn test_reopen() {
+        let path = get_cdb_path("test_reopen.cdb");
+        {
+            let mut store = FileStore::new(path.clone(), OpenMode::Truncate).unwrap();
+            store.set(String::from("key1"), Data::Int(1)).unwrap();
+        }
+        {
+            let mut store = FileStore::new(path.clone(), OpenMode::OpenExisting).unwrap();
+            assert_eq!(1, store.load().unwrap().len());
+            store.set(String::from("key2"), Data::Int(2)).unwrap();
+        }
+
+        let mut store = FileStore::new(path.clone(), OpenMode::OpenOrCreate).unwrap();
+        let tree = store.load().unwrap();
+        assert_eq!(Some(&Data::Int(1)), tree.get("key1"));
+        assert_eq!(Some(&Data::Int(2)), tree.get("key2"));
         store.clear().unwrap();
+        remove_cdb(&path);
+    }
+
+    #[test]
+    fn test_open_mode() {
+        let path = get_cdb_path("test_open_mode.cdb");
+        let _ = fs::remove_file(&path);
+        assert!(FileStore::new(path.clone(), OpenMode::OpenExisting).is_err());
+        assert!(FileStore::new(path.clone(), OpenMode::CreateNew).is_ok());
+        assert!(FileStore::new(path.clone(), OpenMode::CreateNew).is_err());
+
+        let mut store = FileStore::new(path.clone(), OpenMode::OpenExisting).unwrap();
+        store.set(String::from("key"), Data::Int(1)).unwrap();
+        drop(store);
+        let mut store = FileStore::new(path.clone(), OpenMode::Truncate).unwrap();
+        assert!(store.load().unwrap().is_empty());
+        remove_cdb(&path);
+    }
+
+    #[test]
+    fn test_lock() {
+        let path = get_cdb_path("test_lock.cdb");
+        let mut store = FileStore::new(path.clone(), OpenMode::Truncate).unwrap();
+        match FileStore::new(path.clone(), OpenMode::OpenOrCreate) {
+            Err(Error { kind: ErrorKind::DatabaseLocked }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert!(FileStore::open_read_only(path.clone()).is_err());
+
+        store.close().unwrap();
+        assert!(FileStore::new(path.clone(), OpenMode::OpenExisting).is_ok());
+        drop(store);
+        assert!(FileStore::new(path.clone(), OpenMode::OpenExisting).is_ok());
+        remove_cdb(&path);
+    }
+
+    #[test]
+    fn test_open_read_only() {
+        let path = get_cdb_path("test_open_read_only.cdb");
+        let mut store = FileStore::new(path.clone(), OpenMode::Truncate).unwrap();
+        store.set(String::from("key"), Data::Int(1)).unwrap();
+        store.close().unwrap();
+
+        let mut reader1 = FileStore::open_read_only(path.clone()).unwrap();
+        let mut reader2 = FileStore::open_read_only(path.clone()).unwrap();
+        assert_eq!(1, reader1.load().unwrap().len());
+        assert_eq!(1, reader2.load().unwrap().len());
+        match FileStore::new(path.clone(), OpenMode::OpenExisting) {
+            Err(Error { kind: ErrorKind::DatabaseLocked }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        assert!(reader1.set(String::from("key"), Data::Int(2)).is_err());
+        assert!(reader1.clear().is_err());
+        assert!(reader1.compact(&Store::default()).is_err());
+        assert!(reader1.sync().is_ok());
+        assert_eq!(1, reader2.load().unwrap().len());
+        drop(reader1);
+        drop(reader2);
+
+        let store = FileStore::new(path.clone(), OpenMode::Truncate).unwrap();
+        drop(store);
+        assert!(FileStore::open_read_only(get_cdb_path("test_open_read_only_missing.cdb")).is_err());
+        remove_cdb(&path);
+    }
+
+    #[test]
+    fn test_open_read_only_without_lock() {
+        let path = get_cdb_path("test_open_read_only_without_lock.cdb");
+        let mut store = FileStore::new(path.clone(), OpenMode::Truncate).unwrap();
+        store.set(String::from("key"), Data::Int(1)).unwrap();
+        drop(store);
+        fs::remove_file(format!("{}.lock", path)).unwrap();
+
+        // The file itself is locked instead of creating the lock file.
+        let mut reader = FileStore::open_read_only(path.clone()).unwrap();
+        assert_eq!(1, reader.load().unwrap().len());
+        assert!(fs::metadata(format!("{}.lock", path)).is_err());
+        match FileStore::new(path.clone(), OpenMode::OpenExisting) {
+            Err(Error { kind: ErrorKind::DatabaseLocked }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        drop(reader);
+        assert!(FileStore::new(path.clone(), OpenMode::OpenExisting).is_ok());
+        remove_cdb(&path);
+    }
+
+    #[test]
+    fn test_open_read_only_damaged() {
+        let path = get_cdb_path("test_open_read_only_damaged.cdb");
+        let mut store = FileStore::new(path.clone(), OpenMode::Truncate).unwrap();
+        store.set(String::from("key"), Data::Int(1)).unwrap();
+        store.file.write_all(b"garbage").unwrap();
+        let len = store.size().unwrap();
+        drop(store);
+
+        // The damaged tail is reported, but the file is not truncated.
+        let mut store = FileStore::open_read_only(path.clone()).unwrap();
+        assert!(store.load().is_err());
+        let (tree, report) = store.recover().unwrap();
+        assert_eq!(1, tree.len());
+        assert_eq!(7, report.unwrap().dropped_bytes);
+        assert_eq!(len, store.size().unwrap());
+        remove_cdb(&path);
     }
 }
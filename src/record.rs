@@ -0,0 +1,666 @@
+use std::io::Read;
+use data::*;
+use error::*;
+use store::Store;
+use op::Op;
+use checksum::crc32;
+
+// The prefixes of the records in the text format, which is the
+// format of the files written before the binary format.
+const CR: &str = "\r";
+const LF: &str = "\n";
+const SET_PREFIX: &str = "$";
+const REMOVE_PREFIX: &str = "#";
+const BEGIN_PREFIX: &str = "[";
+const COMMIT_PREFIX: &str = "]";
+const CLEAR_PREFIX: &str = "!";
+const CHECKSUM_PREFIX: &str = "@";
+
+// The tags of the records in the binary format.
+const SET_TAG: u8 = 1;
+const REMOVE_TAG: u8 = 2;
+const CLEAR_TAG: u8 = 3;
+const BEGIN_TAG: u8 = 4;
+const COMMIT_TAG: u8 = 5;
+const CHECKPOINT_TAG: u8 = 6;
+const EXPIRE_TAG: u8 = 7;
+const PERSIST_TAG: u8 = 8;
+const APPLY_TAG: u8 = 9;
+
+// The length of the tag and the payload length of a binary record,
+// and the length of its checksum.
+const RECORD_HEADER_LEN: usize = 5;
+const CHECKSUM_LEN: usize = 4;
+
+// One record in the file.
+#[derive(Debug, PartialEq)]
+pub enum Record {
+    Set(String, Data),
+    Remove(String),
+    Clear,
+    Begin(u64),
+    Commit(u64),
+    // The first record of a log segment, which is started by the
+    // checkpoint of the given generation.
+    Checkpoint(u64),
+    // Set the deadline of the key, in milliseconds since the Unix
+    // epoch.
+    Expire(String, u64),
+    // Let the key live forever.
+    Persist(String),
+    // Change the value of the key in place by the operation.
+    Apply(String, Op),
+}
+
+// The result of reading a binary record.
+#[derive(Debug)]
+pub enum Parsed {
+    // The record and its length in bytes.
+    Record(Record, u64),
+    // The end of the file is reached.
+    End,
+    // The end of the file is reached in the middle of a record.
+    Torn,
+}
+
+#[derive(Debug, PartialEq)]
+enum LoadState {
+    Empty,
+    BeforeSetKeyCR,
+    BeforeSetKeyLF,
+    GetSetKey(usize),
+    BeforeSetValCR,
+    BeforeSetValLF,
+    GetSetVal(usize),
+    BeforeRemoveKeyCR,
+    BeforeRemoveKeyLF,
+    GetRemoveKey(usize),
+    BeforeBeginCR,
+    BeforeBeginLF,
+    BeforeCommitCR,
+    BeforeCommitLF,
+    BeforeChecksum,
+    BeforeChecksumCR,
+    BeforeChecksumLF,
+}
+
+// Parse the records from the characters of a file in the text format.
+#[derive(Debug)]
+pub struct TextParser {
+    state: LoadState,
+    buffer: String,
+    cache: String,
+    // The raw string of the current record, to verify its checksum.
+    raw: String,
+    // The parsed record which is waiting for its checksum.
+    record: Option<Record>,
+    // The error of a character read after a record without checksum,
+    // which is returned once the record is.
+    error: Option<Error>,
+    // The offset of the current record, and of the next character.
+    record_offset: u64,
+    offset: u64,
+}
+
+impl Record {
+    // Encode the record into the binary format, which is the tag, the
+    // length of the payload, the payload, then the checksum of all of
+    // them. Integers are little endian.
+    pub fn encode(self) -> Vec<u8> {
+        let (tag, payload) = match self {
+            Record::Set(key, data) => {
+                let mut payload = encode_u32(key.len() as u32);
+                payload.extend_from_slice(key.as_bytes());
+                payload.extend(data.into_bytes());
+                (SET_TAG, payload)
+            }
+            Record::Remove(key) => (REMOVE_TAG, key.into_bytes()),
+            Record::Clear => (CLEAR_TAG, Vec::new()),
+            Record::Begin(id) => (BEGIN_TAG, encode_u64(id)),
+            Record::Commit(id) => (COMMIT_TAG, encode_u64(id)),
+            Record::Checkpoint(generation) => (CHECKPOINT_TAG, encode_u64(generation)),
+            Record::Expire(key, deadline) => {
+                let mut payload = encode_u64(deadline);
+                payload.extend_from_slice(key.as_bytes());
+                (EXPIRE_TAG, payload)
+            }
+            Record::Persist(key) => (PERSIST_TAG, key.into_bytes()),
+            Record::Apply(key, op) => {
+                let mut payload = encode_u32(key.len() as u32);
+                payload.extend_from_slice(key.as_bytes());
+                payload.extend(op.encode());
+                (APPLY_TAG, payload)
+            }
+        };
+
+        let mut bytes = vec![tag];
+        bytes.extend_from_slice(&encode_u32(payload.len() as u32));
+        bytes.extend_from_slice(&payload);
+        let checksum = crc32(&bytes);
+        bytes.extend_from_slice(&encode_u32(checksum));
+        bytes
+    }
+
+    // Apply a data record to the store.
+    pub fn apply(self, store: &mut Store) -> Result<()> {
+        match self {
+            Record::Set(key, data) => {
+                store.insert(key, data, 0);
+            }
+            Record::Remove(key) => {
+                store.remove(&key);
+            }
+            Record::Clear => *store = Store::default(),
+            Record::Expire(key, deadline) => {
+                store.set_deadline(&key, Some(deadline), 0);
+            }
+            Record::Persist(key) => {
+                store.set_deadline(&key, None, 0);
+            }
+            Record::Apply(key, op) => store.apply(&key, op, 0)?,
+            Record::Begin(_) | Record::Commit(_) | Record::Checkpoint(_) => unreachable!(),
+        }
+        Ok(())
+    }
+
+    // Read the next record in the binary format.
+    pub fn read<R: Read>(reader: &mut R) -> Result<Parsed> {
+        let mut header = Vec::new();
+        reader.by_ref().take(RECORD_HEADER_LEN as u64).read_to_end(&mut header)?;
+        if header.is_empty() {
+            return Ok(Parsed::End);
+        }
+        if header.len() < RECORD_HEADER_LEN {
+            return Ok(Parsed::Torn);
+        }
+        if header[0] < SET_TAG || header[0] > APPLY_TAG {
+            return Err(Error::new(ErrorKind::InvalidSerializedString));
+        }
+
+        // Read only what the file has, in case the length is damaged.
+        let payload_len = decode_u32(&header[1..]) as usize;
+        let mut rest = Vec::new();
+        reader
+            .by_ref()
+            .take((payload_len + CHECKSUM_LEN) as u64)
+            .read_to_end(&mut rest)?;
+        if rest.len() < payload_len + CHECKSUM_LEN {
+            return Ok(Parsed::Torn);
+        }
+
+        let (payload, checksum) = rest.split_at(payload_len);
+        header.extend_from_slice(payload);
+        if crc32(&header) != decode_u32(checksum) {
+            return Err(Error::new(ErrorKind::ChecksumMismatch));
+        }
+
+        let record = Self::decode(header[0], payload)?;
+        Ok(Parsed::Record(record, (RECORD_HEADER_LEN + rest.len()) as u64))
+    }
+
+    fn decode(tag: u8, payload: &[u8]) -> Result<Record> {
+        match tag {
+            SET_TAG => {
+                let (key, value) = split_key(payload)?;
+                Ok(Record::Set(key, Data::try_from_bytes(value.to_vec())?))
+            }
+            REMOVE_TAG => Ok(Record::Remove(String::from_utf8(payload.to_vec())?)),
+            CLEAR_TAG => Ok(Record::Clear),
+            BEGIN_TAG if payload.len() == 8 => Ok(Record::Begin(decode_u64(payload))),
+            COMMIT_TAG if payload.len() == 8 => Ok(Record::Commit(decode_u64(payload))),
+            CHECKPOINT_TAG if payload.len() == 8 => {
+                Ok(Record::Checkpoint(decode_u64(payload)))
+            }
+            EXPIRE_TAG if payload.len() >= 8 => {
+                let key = String::from_utf8(payload[8..].to_vec())?;
+                Ok(Record::Expire(key, decode_u64(&payload[..8])))
+            }
+            PERSIST_TAG => Ok(Record::Persist(String::from_utf8(payload.to_vec())?)),
+            APPLY_TAG => {
+                let (key, op) = split_key(payload)?;
+                Ok(Record::Apply(key, Op::decode(op)?))
+            }
+            _ => Err(Error::new(ErrorKind::InvalidSerializedString)),
+        }
+    }
+}
+
+// Split a payload which starts with the length of the key and the key.
+fn split_key(payload: &[u8]) -> Result<(String, &[u8])> {
+    if payload.len() < 4 {
+        return Err(Error::new(ErrorKind::InvalidSerializedString));
+    }
+    let key_len = decode_u32(&payload[..4]) as usize;
+    if payload.len() < 4 + key_len {
+        return Err(Error::new(ErrorKind::InvalidSerializedString));
+    }
+    let key = String::from_utf8(payload[4..4 + key_len].to_vec())?;
+    Ok((key, &payload[4 + key_len..]))
+}
+
+pub fn encode_u32(n: u32) -> Vec<u8> {
+    (0..4).map(|i| (n >> (8 * i)) as u8).collect()
+}
+
+pub fn encode_u64(n: u64) -> Vec<u8> {
+    (0..8).map(|i| (n >> (8 * i)) as u8).collect()
+}
+
+pub fn decode_u32(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .take(4)
+        .enumerate()
+        .fold(0, |n, (i, byte)| n | (*byte as u32) << (8 * i))
+}
+
+pub fn decode_u64(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .take(8)
+        .enumerate()
+        .fold(0, |n, (i, byte)| n | (*byte as u64) << (8 * i))
+}
+
+impl TextParser {
+    pub fn new() -> TextParser {
+        TextParser {
+            state: LoadState::Empty,
+            buffer: String::new(),
+            cache: String::new(),
+            raw: String::new(),
+            record: None,
+            error: None,
+            record_offset: 0,
+            offset: 0,
+        }
+    }
+
+    // Whether a record is partially read.
+    pub fn is_pending(&self) -> bool {
+        self.state != LoadState::Empty
+    }
+
+    // The offset of the last record which is not fully read.
+    pub fn record_offset(&self) -> u64 {
+        self.record_offset
+    }
+
+    // Feed the next character of the file. Return the record and its
+    // offset once both the record and its checksum are read. The
+    // checksum is optional, since the records written by the earlier
+    // versions have none, then the record is returned once the next
+    // one starts.
+    pub fn feed(&mut self, ch: char) -> Result<Option<(Record, u64)>> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        let char_string = ch.to_string();
+        if self.state == LoadState::BeforeChecksum && char_string != CHECKSUM_PREFIX {
+            let record = self.record.take().map(|record| (record, self.record_offset));
+            self.state = LoadState::Empty;
+            if let Err(err) = self.feed(ch) {
+                self.error = Some(err);
+            }
+            return Ok(record);
+        }
+        if self.state == LoadState::Empty {
+            self.record_offset = self.offset;
+            self.raw.clear();
+        }
+        self.offset += ch.len_utf8() as u64;
+        if self.record.is_none() {
+            self.raw.push(ch);
+        }
+
+        let record = match self.state {
+            LoadState::Empty => {
+                if char_string == CLEAR_PREFIX {
+                    Record::Clear
+                } else {
+                    self.state = if char_string == SET_PREFIX {
+                        LoadState::BeforeSetKeyCR
+                    } else if char_string == REMOVE_PREFIX {
+                        LoadState::BeforeRemoveKeyCR
+                    } else if char_string == BEGIN_PREFIX {
+                        LoadState::BeforeBeginCR
+                    } else if char_string == COMMIT_PREFIX {
+                        LoadState::BeforeCommitCR
+                    } else {
+                        return Err(Error::new(ErrorKind::InvalidSerializedString));
+                    };
+                    return Ok(None);
+                }
+            }
+            LoadState::BeforeSetKeyCR => {
+                if char_string == CR {
+                    self.state = LoadState::BeforeSetKeyLF;
+                    return Ok(None);
+                }
+                self.buffer.push(ch);
+                return Ok(None);
+            }
+            LoadState::BeforeSetKeyLF => {
+                if char_string != LF {
+                    return Err(Error::new(ErrorKind::InvalidSerializedString));
+                }
+
+                let key_len = String::from(&self.buffer[0..self.buffer.len()]).parse()?;
+                self.state = LoadState::GetSetKey(key_len);
+                self.buffer.clear();
+                return Ok(None);
+            }
+            LoadState::GetSetKey(len) => {
+                // The length is counted in bytes rather than in
+                // characters.
+                self.buffer.push(ch);
+                if self.buffer.len() < len {
+                    return Ok(None);
+                }
+                if self.buffer.len() > len {
+                    return Err(Error::new(ErrorKind::InvalidSerializedString));
+                }
+
+                self.cache = self.buffer.clone();
+                self.buffer.clear();
+                self.state = LoadState::BeforeSetValCR;
+                return Ok(None);
+            }
+            LoadState::BeforeSetValCR => {
+                if char_string == CR {
+                    self.state = LoadState::BeforeSetValLF;
+                    return Ok(None);
+                }
+                self.buffer.push(ch);
+                return Ok(None);
+            }
+            LoadState::BeforeSetValLF => {
+                if char_string != LF {
+                    return Err(Error::new(ErrorKind::InvalidSerializedString));
+                }
+
+                let val_len = String::from(&self.buffer[0..self.buffer.len()]).parse()?;
+                self.state = LoadState::GetSetVal(val_len);
+                self.buffer.clear();
+                return Ok(None);
+            }
+            LoadState::GetSetVal(len) => {
+                // The length is counted in bytes rather than in
+                // characters.
+                self.buffer.push(ch);
+                if self.buffer.len() < len {
+                    return Ok(None);
+                }
+                if self.buffer.len() > len {
+                    return Err(Error::new(ErrorKind::InvalidSerializedString));
+                }
+
+                let record = Record::Set(self.cache.clone(), Data::try_from(self.buffer.clone())?);
+                self.cache.clear();
+                self.buffer.clear();
+                record
+            }
+            LoadState::BeforeRemoveKeyCR => {
+                if char_string == CR {
+                    self.state = LoadState::BeforeRemoveKeyLF;
+                    return Ok(None);
+                }
+                self.buffer.push(ch);
+                return Ok(None);
+            }
+            LoadState::BeforeRemoveKeyLF => {
+                if char_string != LF {
+                    return Err(Error::new(ErrorKind::InvalidSerializedString));
+                }
+
+                let key_len = String::from(&self.buffer[0..self.buffer.len()]).parse()?;
+                self.state = LoadState::GetRemoveKey(key_len);
+                self.buffer.clear();
+                return Ok(None);
+            }
+            LoadState::GetRemoveKey(len) => {
+                // The length is counted in bytes rather than in
+                // characters.
+                self.buffer.push(ch);
+                if self.buffer.len() < len {
+                    return Ok(None);
+                }
+                if self.buffer.len() > len {
+                    return Err(Error::new(ErrorKind::InvalidSerializedString));
+                }
+
+                let record = Record::Remove(self.buffer.clone());
+                self.buffer.clear();
+                record
+            }
+            LoadState::BeforeBeginCR |
+            LoadState::BeforeCommitCR |
+            LoadState::BeforeChecksumCR => {
+                if char_string == CR {
+                    self.state = match self.state {
+                        LoadState::BeforeBeginCR => LoadState::BeforeBeginLF,
+                        LoadState::BeforeCommitCR => LoadState::BeforeCommitLF,
+                        _ => LoadState::BeforeChecksumLF,
+                    };
+                    return Ok(None);
+                }
+                self.buffer.push(ch);
+                return Ok(None);
+            }
+            LoadState::BeforeBeginLF |
+            LoadState::BeforeCommitLF => {
+                if char_string != LF {
+                    return Err(Error::new(ErrorKind::InvalidSerializedString));
+                }
+
+                let id = String::from(&self.buffer[0..self.buffer.len()]).parse()?;
+                self.buffer.clear();
+                if self.state == LoadState::BeforeBeginLF {
+                    Record::Begin(id)
+                } else {
+                    Record::Commit(id)
+                }
+            }
+            LoadState::BeforeChecksum => {
+                self.state = LoadState::BeforeChecksumCR;
+                return Ok(None);
+            }
+            LoadState::BeforeChecksumLF => {
+                if char_string != LF {
+                    return Err(Error::new(ErrorKind::InvalidSerializedString));
+                }
+
+                let checksum: u32 = String::from(&self.buffer[0..self.buffer.len()]).parse()?;
+                self.buffer.clear();
+                if checksum != crc32(self.raw.as_bytes()) {
+                    return Err(Error::new(ErrorKind::ChecksumMismatch));
+                }
+
+                self.state = LoadState::Empty;
+                return Ok(self.record.take().map(|record| (record, self.record_offset)));
+            }
+        };
+
+        self.record = Some(record);
+        self.state = LoadState::BeforeChecksum;
+        Ok(None)
+    }
+
+    // Finish at the end of the file. Return the last record if it has
+    // no checksum.
+    pub fn finish(&mut self) -> Result<Option<(Record, u64)>> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        if self.state != LoadState::BeforeChecksum {
+            return Ok(None);
+        }
+        self.state = LoadState::Empty;
+        Ok(self.record.take().map(|record| (record, self.record_offset)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use op::Side;
+
+    fn parse_text(text: &str) -> Result<Vec<Record>> {
+        let mut parser = TextParser::new();
+        let mut records = Vec::new();
+        for ch in text.chars() {
+            if let Some((record, _)) = parser.feed(ch)? {
+                records.push(record);
+            }
+        }
+        if let Some((record, _)) = parser.finish()? {
+            records.push(record);
+        }
+        Ok(records)
+    }
+
+    fn with_checksum(record: &str) -> String {
+        format!("{}@{}\r\n", record, crc32(record.as_bytes()))
+    }
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(vec![3, 0, 0, 0, 0, 0xCD, 0x8D, 0x82, 0x81],
+                   Record::Clear.encode());
+
+        let bytes = Record::Set(String::from("key"), Data::Int(1)).encode();
+        assert_eq!(vec![1, 11, 0, 0, 0, 3, 0, 0, 0, b'k', b'e', b'y', b':', b'1', b'\r', b'\n'],
+                   &bytes[..16]);
+        assert_eq!(crc32(&bytes[..16]), decode_u32(&bytes[16..]));
+    }
+
+    #[test]
+    fn test_read() {
+        let records = vec![Record::Begin(1),
+                           Record::Set(String::from("键"), Data::String(String::from("值"))),
+                           Record::Remove(String::from("key")),
+                           Record::Clear,
+                           Record::Commit(1),
+                           Record::Checkpoint(2),
+                           Record::Expire(String::from("键"), 1000),
+                           Record::Persist(String::from("键")),
+                           Record::Apply(String::from("键"), Op::Pop(Side::Left))];
+        let mut bytes = Vec::new();
+        for record in records {
+            bytes.extend(record.encode());
+        }
+
+        let mut reader = &bytes[..];
+        match Record::read(&mut reader).unwrap() {
+            Parsed::Record(Record::Begin(1), 17) => {}
+            parsed => panic!("unexpected record: {:?}", parsed),
+        }
+        match Record::read(&mut reader).unwrap() {
+            Parsed::Record(Record::Set(key, Data::String(value)), _) => {
+                assert_eq!("键", key);
+                assert_eq!("值", value);
+            }
+            parsed => panic!("unexpected record: {:?}", parsed),
+        }
+        for _ in 0..3 {
+            match Record::read(&mut reader).unwrap() {
+                Parsed::Record(_, _) => {}
+                parsed => panic!("unexpected record: {:?}", parsed),
+            }
+        }
+        match Record::read(&mut reader).unwrap() {
+            Parsed::Record(Record::Checkpoint(2), 17) => {}
+            parsed => panic!("unexpected record: {:?}", parsed),
+        }
+        match Record::read(&mut reader).unwrap() {
+            Parsed::Record(Record::Expire(key, 1000), 20) => assert_eq!("键", key),
+            parsed => panic!("unexpected record: {:?}", parsed),
+        }
+        match Record::read(&mut reader).unwrap() {
+            Parsed::Record(Record::Persist(key), 12) => assert_eq!("键", key),
+            parsed => panic!("unexpected record: {:?}", parsed),
+        }
+        match Record::read(&mut reader).unwrap() {
+            Parsed::Record(Record::Apply(key, Op::Pop(Side::Left)), 18) => assert_eq!("键", key),
+            parsed => panic!("unexpected record: {:?}", parsed),
+        }
+        match Record::read(&mut reader).unwrap() {
+            Parsed::End => {}
+            parsed => panic!("unexpected record: {:?}", parsed),
+        }
+    }
+
+    #[test]
+    fn test_read_torn() {
+        let bytes = Record::Remove(String::from("key")).encode();
+        for len in 1..bytes.len() {
+            match Record::read(&mut &bytes[..len]).unwrap() {
+                Parsed::Torn => {}
+                parsed => panic!("unexpected record: {:?}", parsed),
+            }
+        }
+    }
+
+    #[test]
+    fn test_read_damaged() {
+        let mut bytes = Record::Remove(String::from("key")).encode();
+        bytes[6] = b'x';
+        assert!(Record::read(&mut &bytes[..]).is_err());
+
+        let mut bytes = vec![10, 0, 0, 0, 0];
+        let checksum = crc32(&bytes);
+        bytes.extend(encode_u32(checksum));
+        assert!(Record::read(&mut &bytes[..]).is_err());
+    }
+
+    #[test]
+    fn test_encode_integer() {
+        assert_eq!(vec![0x78, 0x56, 0x34, 0x12], encode_u32(0x12345678));
+        assert_eq!(0x12345678, decode_u32(&encode_u32(0x12345678)));
+        assert_eq!(u64::MAX, decode_u64(&encode_u64(u64::MAX)));
+    }
+
+    #[test]
+    fn test_parse_text() {
+        let text = [with_checksum("[1\r\n"),
+                    with_checksum("$3\r\n键4\r\n:1\r\n"),
+                    with_checksum("#3\r\n键"),
+                    with_checksum("!"),
+                    with_checksum("]1\r\n")]
+                .concat();
+        assert_eq!(vec![Record::Begin(1),
+                        Record::Set(String::from("键"), Data::Int(1)),
+                        Record::Remove(String::from("键")),
+                        Record::Clear,
+                        Record::Commit(1)],
+                   parse_text(&text).unwrap());
+    }
+
+    #[test]
+    fn test_parse_text_damaged() {
+        assert!(parse_text(&with_checksum("$3\r\nkey4\r\n:1\r\n").replace(":1", ":2")).is_err());
+        assert!(parse_text(&with_checksum("$2\r\nkey4\r\n:1\r\n")).is_err());
+        assert!(parse_text("%").is_err());
+    }
+
+    #[test]
+    fn test_parse_text_without_checksum() {
+        // The records written before the checksums were added.
+        let text = ["[1\r\n", "$3\r\n键4\r\n:1\r\n", &with_checksum("#3\r\n键"), "!", "]1\r\n"]
+            .concat();
+        assert_eq!(vec![Record::Begin(1),
+                        Record::Set(String::from("键"), Data::Int(1)),
+                        Record::Remove(String::from("键")),
+                        Record::Clear,
+                        Record::Commit(1)],
+                   parse_text(&text).unwrap());
+
+        // The record before an invalid character is still returned.
+        let mut parser = TextParser::new();
+        for ch in "#1\r\na".chars() {
+            assert!(parser.feed(ch).unwrap().is_none());
+        }
+        assert_eq!(Some((Record::Remove(String::from("a")), 0)), parser.feed('%').unwrap());
+        assert!(parser.finish().is_err());
+    }
+}
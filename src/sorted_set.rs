@@ -0,0 +1,165 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+// A set of members ordered by their scores, then by the members
+// themselves when the scores are equal. The order is kept in a sorted
+// vector, so the rank of a member and the members in a range of scores
+// are found by binary search. A change moves the members after it,
+// which costs no more than the copy of the set made by the write.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SortedSet {
+    scores: BTreeMap<String, f64>,
+    ordered: Vec<(Score, String)>,
+}
+
+// A score ordered by `f64::total_cmp`. The scores are never NaN.
+#[derive(Debug, Clone, Copy)]
+struct Score(f64);
+
+impl SortedSet {
+    pub fn new() -> SortedSet {
+        SortedSet::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn score(&self, member: &str) -> Option<f64> {
+        self.scores.get(member).cloned()
+    }
+
+    // Return the position of the member in the order, starting from 0.
+    pub fn rank(&self, member: &str) -> Option<usize> {
+        let score = self.score(member)?;
+        self.position(score, member).ok()
+    }
+
+    // Set the score of the member, return its previous score. The
+    // score must not be NaN.
+    pub fn insert(&mut self, member: String, score: f64) -> Option<f64> {
+        debug_assert!(!score.is_nan());
+        let previous = self.remove(&member);
+        let index = self.position(score, &member).unwrap_err();
+        self.ordered.insert(index, (Score(score), member.clone()));
+        self.scores.insert(member, score);
+        previous
+    }
+
+    // Remove the member, return its score.
+    pub fn remove(&mut self, member: &str) -> Option<f64> {
+        let score = self.scores.remove(member)?;
+        let index = self.position(score, member).unwrap();
+        self.ordered.remove(index);
+        Some(score)
+    }
+
+    // Return the member with the lowest score.
+    pub fn first(&self) -> Option<(&String, f64)> {
+        self.ordered.first().map(|(score, member)| (member, score.0))
+    }
+
+    // Iterate over the members with their scores, in order.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&String, f64)> {
+        self.ordered.iter().map(|(score, member)| (member, score.0))
+    }
+
+    // Iterate over the members whose score is in [min, max], in order.
+    pub fn range_by_score(&self, min: f64, max: f64) -> impl Iterator<Item = (&String, f64)> {
+        let start = self.ordered.partition_point(|(score, _)| *score < Score(min));
+        let end = self.ordered.partition_point(|(score, _)| *score <= Score(max));
+        self.ordered[start..end.max(start)]
+            .iter()
+            .map(|(score, member)| (member, score.0))
+    }
+
+    // Search the member with the score in the order.
+    fn position(&self, score: f64, member: &str) -> Result<usize, usize> {
+        let score = Score(score);
+        self.ordered
+            .binary_search_by(|(other, other_member)| {
+                                  other.cmp(&score).then_with(|| other_member.as_str().cmp(member))
+                              })
+    }
+}
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Score) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Score) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Score) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn members(set: &SortedSet) -> Vec<(&str, f64)> {
+        set.iter().map(|(member, score)| (member.as_str(), score)).collect()
+    }
+
+    #[test]
+    fn test_insert_remove() {
+        let mut set = SortedSet::new();
+        assert_eq!(None, set.insert(String::from("b"), 2.0));
+        assert_eq!(None, set.insert(String::from("a"), 2.0));
+        assert_eq!(None, set.insert(String::from("c"), -1.0));
+        assert_eq!(vec![("c", -1.0), ("a", 2.0), ("b", 2.0)], members(&set));
+
+        assert_eq!(Some(2.0), set.insert(String::from("a"), 3.0));
+        assert_eq!(vec![("c", -1.0), ("b", 2.0), ("a", 3.0)], members(&set));
+        assert_eq!(Some(2.0), set.remove("b"));
+        assert_eq!(None, set.remove("b"));
+        assert_eq!(2, set.len());
+        assert_eq!(Some((&String::from("c"), -1.0)), set.first());
+    }
+
+    #[test]
+    fn test_rank_score() {
+        let mut set = SortedSet::new();
+        for (i, member) in ["a", "b", "c"].iter().enumerate() {
+            set.insert(String::from(*member), i as f64);
+        }
+        assert_eq!(Some(2), set.rank("c"));
+        assert_eq!(Some(0), set.rank("a"));
+        assert_eq!(None, set.rank("d"));
+        assert_eq!(Some(1.0), set.score("b"));
+        assert_eq!(None, set.score("d"));
+        // The members with equal scores are ordered by themselves.
+        set.insert(String::from("d"), 1.0);
+        assert_eq!(Some(1), set.rank("b"));
+        assert_eq!(Some(2), set.rank("d"));
+        assert_eq!(Some(3), set.rank("c"));
+    }
+
+    #[test]
+    fn test_range_by_score() {
+        let mut set = SortedSet::new();
+        set.insert(String::from("a"), 1.0);
+        set.insert(String::from("b"), 2.0);
+        set.insert(String::from("c"), 3.0);
+        let range: Vec<&String> = set.range_by_score(1.5, 3.0).map(|(member, _)| member).collect();
+        assert_eq!(vec!["b", "c"], range);
+        assert_eq!(0, set.range_by_score(3.5, 10.0).count());
+        assert_eq!(0, set.range_by_score(2.0, 1.0).count());
+        assert_eq!(3, set.range_by_score(f64::NEG_INFINITY, f64::INFINITY).count());
+        assert_eq!(1, set.range_by_score(3.0, 3.0).count());
+    }
+}
@@ -0,0 +1,352 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use data::*;
+use error::*;
+use op::Op;
+use map::Map;
+
+// The items of the database, with the version of every key. A key
+// gets a new version every time it is changed, the keys loaded from
+// the disk have the base version, which is the last version handed out
+// before they were saved. A key could have a deadline, in
+// milliseconds since the Unix epoch, after which it is expired and
+// not seen anymore, though it is kept until it is removed. The maps
+// are shared by the clones, so a store is cheap to clone, and a change
+// only copies what it touches.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Store {
+    data: Map<String, Data>,
+    versions: Map<String, u64>,
+    deadlines: Map<String, u64>,
+    base_version: u64,
+}
+
+// Everything about a key, which is returned when the key is changed
+// so the change could be undone.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub value: Data,
+    pub version: u64,
+    pub deadline: Option<u64>,
+}
+
+impl Store {
+    pub fn new(data: BTreeMap<String, Data>) -> Store {
+        Store {
+            data: data.into_iter().collect(),
+            ..Store::default()
+        }
+    }
+
+    // All the items, including the expired ones.
+    pub fn data(&self) -> &Map<String, Data> {
+        &self.data
+    }
+
+    pub fn deadlines(&self) -> &Map<String, u64> {
+        &self.deadlines
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Data> {
+        if self.is_expired(key) {
+            return None;
+        }
+        self.data.get(key)
+    }
+
+    pub fn get_with_version(&self, key: &str) -> Option<(&Data, u64)> {
+        self.get(key).map(|value| (value, self.version(key)))
+    }
+
+    // Return how long the key lives, or `None` if the key does not
+    // exist or lives forever.
+    pub fn ttl(&self, key: &str) -> Option<Duration> {
+        let deadline = match self.deadlines.get(key) {
+            Some(&deadline) => deadline,
+            None => return None,
+        };
+        let now = now();
+        if deadline <= now {
+            return None;
+        }
+        Some(Duration::from_millis(deadline - now))
+    }
+
+    pub fn len(&self) -> usize {
+        if self.deadlines.is_empty() {
+            return self.data.len();
+        }
+        let now = now();
+        self.data.len() - self.deadlines.iter().filter(|&(_, &deadline)| deadline <= now).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    // Return the keys which are expired at the given time.
+    pub fn expired_keys(&self, now: u64) -> Vec<String> {
+        self.deadlines
+            .iter()
+            .filter(|&(_, &deadline)| deadline <= now)
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    // Set the value of the key with the version, which lives forever,
+    // and return the previous entry.
+    pub fn insert(&mut self, key: String, value: Data, version: u64) -> Option<Entry> {
+        let previous_version = self.set_version(&key, version);
+        let previous_deadline = self.deadlines.remove(&key);
+        self.data
+            .insert(key, value)
+            .map(|value| {
+                     Entry {
+                         value,
+                         version: previous_version,
+                         deadline: previous_deadline,
+                     }
+                 })
+    }
+
+    // Remove the key, return the previous entry.
+    pub fn remove(&mut self, key: &str) -> Option<Entry> {
+        let version = self.versions.remove(key).unwrap_or(self.base_version);
+        let deadline = self.deadlines.remove(key);
+        self.data
+            .remove(key)
+            .map(|value| {
+                     Entry {
+                         value,
+                         version,
+                         deadline,
+                     }
+                 })
+    }
+
+    // Set the deadline of an existing key with the version, or let it
+    // live forever if the deadline is `None`. Return the previous
+    // entry, or `None` without changing anything if the key does not
+    // exist.
+    pub fn set_deadline(&mut self,
+                        key: &str,
+                        deadline: Option<u64>,
+                        version: u64)
+                        -> Option<Entry> {
+        let value = match self.data.get(key) {
+            Some(value) => value.clone(),
+            None => return None,
+        };
+        let previous_deadline = match deadline {
+            Some(deadline) => self.deadlines.insert(String::from(key), deadline),
+            None => self.deadlines.remove(key),
+        };
+        Some(Entry {
+                 value,
+                 version: self.set_version(key, version),
+                 deadline: previous_deadline,
+             })
+    }
+
+    // Change the value of the key by the operation with the version,
+    // the key is removed if its value is emptied. Nothing is changed
+    // if the operation fails.
+    pub fn apply(&mut self, key: &str, op: Op, version: u64) -> Result<()> {
+        let mut value = self.data.remove(key);
+        let result = op.apply(&mut value);
+        match value {
+            Some(value) => {
+                self.data.insert(String::from(key), value);
+                if result.is_ok() {
+                    self.set_version(key, version);
+                }
+            }
+            None => {
+                self.versions.remove(key);
+                self.deadlines.remove(key);
+            }
+        }
+        result
+    }
+
+    // Return a copy of everything about the key, including when it is
+    // expired.
+    pub fn entry(&self, key: &str) -> Option<Entry> {
+        self.data
+            .get(key)
+            .map(|value| {
+                     Entry {
+                         value: value.clone(),
+                         version: self.version(key),
+                         deadline: self.deadlines.get(key).cloned(),
+                     }
+                 })
+    }
+
+    // Put back the previous entry of the key, or remove the key if it
+    // did not exist.
+    pub fn restore(&mut self, key: String, previous: Option<Entry>) {
+        match previous {
+            Some(entry) => {
+                self.set_version(&key, entry.version);
+                match entry.deadline {
+                    Some(deadline) => self.deadlines.insert(key.clone(), deadline),
+                    None => self.deadlines.remove(&key),
+                };
+                self.data.insert(key, entry.value);
+            }
+            None => {
+                self.remove(&key);
+            }
+        }
+    }
+
+    pub fn version(&self, key: &str) -> u64 {
+        self.versions.get(key).cloned().unwrap_or(self.base_version)
+    }
+
+    pub fn base_version(&self) -> u64 {
+        self.base_version
+    }
+
+    // Let all the keys without a version of their own have the base
+    // version.
+    pub fn set_base_version(&mut self, version: u64) {
+        self.base_version = version;
+    }
+
+    // Set the version of the key, return the previous one. The base
+    // version is not recorded.
+    fn set_version(&mut self, key: &str, version: u64) -> u64 {
+        let previous = if version == self.base_version {
+            self.versions.remove(key)
+        } else {
+            self.versions.insert(String::from(key), version)
+        };
+        previous.unwrap_or(self.base_version)
+    }
+
+    pub fn is_expired(&self, key: &str) -> bool {
+        match self.deadlines.get(key) {
+            Some(&deadline) => deadline <= now(),
+            None => false,
+        }
+    }
+}
+
+impl Entry {
+    // Return the value if it is not expired.
+    pub fn into_value(self) -> Option<Data> {
+        match self.deadline {
+            Some(deadline) if deadline <= now() => None,
+            _ => Some(self.value),
+        }
+    }
+}
+
+// Return the current time in milliseconds since the Unix epoch.
+pub fn now() -> u64 {
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis())
+}
+
+// Return the deadline of a key which lives for the duration from now.
+pub fn deadline_after(ttl: Duration) -> u64 {
+    let ttl = ttl.as_secs()
+        .saturating_mul(1000)
+        .saturating_add(u64::from(ttl.subsec_millis()));
+    now().saturating_add(ttl)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use op::Side;
+
+    #[test]
+    fn test_versions() {
+        let mut data = BTreeMap::new();
+        data.insert(String::from("a"), Data::Int(1));
+        let mut store = Store::new(data);
+        assert_eq!(Some((&Data::Int(1), 0)), store.get_with_version("a"));
+
+        let previous = store.insert(String::from("a"), Data::Int(2), 3).unwrap();
+        assert_eq!((Data::Int(1), 0), (previous.value, previous.version));
+        assert_eq!(Some((&Data::Int(2), 3)), store.get_with_version("a"));
+        assert!(store.insert(String::from("b"), Data::Int(3), 4).is_none());
+        assert_eq!(4, store.remove("b").unwrap().version);
+        assert_eq!(None, store.get_with_version("b"));
+        assert!(store.remove("b").is_none());
+    }
+
+    #[test]
+    fn test_restore() {
+        let mut store = Store::default();
+        store.insert(String::from("a"), Data::Int(1), 1);
+        let previous = store.insert(String::from("a"), Data::Int(2), 2);
+        store.restore(String::from("a"), previous);
+        assert_eq!(Some((&Data::Int(1), 1)), store.get_with_version("a"));
+
+        let previous = store.set_deadline("a", Some(now() + 60 * 1000), 3);
+        assert!(store.ttl("a").is_some());
+        store.restore(String::from("a"), previous);
+        assert_eq!(Some((&Data::Int(1), 1)), store.get_with_version("a"));
+        assert!(store.ttl("a").is_none());
+
+        store.restore(String::from("a"), None);
+        assert!(store.is_empty());
+        assert_eq!(0, store.version("a"));
+        assert!(store.versions.is_empty());
+    }
+
+    #[test]
+    fn test_deadlines() {
+        let mut store = Store::default();
+        store.insert(String::from("a"), Data::Int(1), 1);
+        store.insert(String::from("b"), Data::Int(2), 2);
+        assert!(store.set_deadline("c", Some(0), 3).is_none());
+        assert!(store.set_deadline("a", Some(now() - 1), 3).is_some());
+        store.set_deadline("b", Some(deadline_after(Duration::from_secs(60))), 4);
+
+        assert!(store.get("a").is_none());
+        assert!(!store.contains_key("a"));
+        assert!(store.ttl("a").is_none());
+        assert_eq!(1, store.len());
+        assert_eq!(vec![String::from("a")], store.expired_keys(now()));
+        assert!(store.ttl("b").unwrap() > Duration::from_secs(59));
+
+        // Setting the value again lets the key live forever.
+        assert!(store.insert(String::from("a"), Data::Int(3), 5).unwrap().into_value().is_none());
+        assert_eq!(Some(&Data::Int(3)), store.get("a"));
+        assert!(store.ttl("a").is_none());
+        store.set_deadline("b", None, 6);
+        assert!(store.ttl("b").is_none());
+        assert!(store.deadlines().is_empty());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut store = Store::default();
+        store.apply("a", Op::Push(Side::Left, Data::Int(1)), 1).unwrap();
+        store.set_deadline("a", Some(deadline_after(Duration::from_secs(60))), 2);
+        store.apply("a", Op::Push(Side::Left, Data::Int(2)), 3).unwrap();
+        let entry = store.entry("a").unwrap();
+        assert_eq!(3, entry.version);
+        assert!(entry.deadline.is_some());
+
+        store.insert(String::from("b"), Data::Int(1), 4);
+        assert!(store.apply("b", Op::Pop(Side::Left), 5).is_err());
+        assert_eq!(Some((&Data::Int(1), 4)), store.get_with_version("b"));
+
+        store.apply("a", Op::Trim(1, 0), 6).unwrap();
+        assert!(store.entry("a").is_none());
+        assert!(store.deadlines().is_empty());
+        assert_eq!(0, store.version("a"));
+    }
+}
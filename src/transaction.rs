@@ -1,20 +1,57 @@
-use std::collections::*;
-use std::ops::Deref;
-use std::cell::RefCell;
-use persist::Persistable;
+use std::ops::Range;
+use std::collections::{BTreeMap, BTreeSet};
+use std::mem;
+use std::time::Duration;
+use std::sync::Arc;
+use persist::{Persistable, CompactPolicy, CheckpointPolicy};
+use store::{self, Store, Entry};
+use op::{self, Op, Side};
 use data::*;
 use error::*;
+use iter::*;
 
+// How to undo a change of the current transaction.
 #[derive(Debug)]
-struct Item {
-    key: String,
-    value: Option<Data>,
+enum RollbackItem {
+    // Restore the previous entry of the key, or remove the key if it
+    // did not exist.
+    Restore(String, Option<Entry>),
+    // Restore the whole store before it was cleared.
+    Clear(Arc<Store>),
+    // Apply the inverse of an operation on the key, and put back the
+    // previous version.
+    Undo(String, Op, u64),
 }
 
-impl Item {
-    fn new(k: String, v: Option<Data>) -> Item {
-        Item { key: k, value: v }
-    }
+// What a key is expected to be by `compare_and_swap` and
+// `compare_and_remove`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expected {
+    // The key does not exist.
+    Absent,
+    // The key exists with the version.
+    Version(u64),
+    // The key exists with the value.
+    Value(Data),
+}
+
+// A point in the current transaction which could be rolled back to,
+// without undoing the changes before it.
+#[derive(Debug)]
+pub struct Savepoint {
+    rollback_len: usize,
+    sync_len: usize,
+}
+
+// A change which should be synced to the persist store.
+#[derive(Debug, Clone)]
+enum SyncItem {
+    Set(String, Data),
+    Remove(String),
+    Clear,
+    Expire(String, u64),
+    Persist(String),
+    Apply(String, Op),
 }
 
 // A read-only transaction on the dasebase.
@@ -22,97 +59,500 @@ pub trait ReadTransaction<K>
     where K: Into<String> + Ord + Clone
 {
     fn get(&self, key: K) -> Option<&Data>;
+    // Return the value of the key with its version, which is increased
+    // every time the key is changed. Once the database is reopened, the
+    // loaded keys have a version newer than all the versions handed
+    // out before.
+    fn get_with_version(&self, key: K) -> Option<(&Data, u64)>;
+    // Return how long the key lives, or `None` if the key does not
+    // exist or lives forever.
+    fn ttl(&self, key: K) -> Option<Duration>;
+    // The expired keys are not counted.
     fn len(&self) -> usize;
     fn is_empty(&self) -> bool;
     fn contains_key(&self, key: K) -> bool;
+    // Iterate over all the items, ordered by key.
+    fn iter(&self) -> Iter<'_>;
+    // Iterate over all the keys, in order.
+    fn keys(&self) -> Keys<'_>;
+    // Iterate over the items whose key is in [start, end).
+    fn range(&self, range: Range<K>) -> Iter<'_>;
+    // Iterate over the items whose key starts with the prefix.
+    fn scan_prefix(&self, prefix: K) -> Iter<'_>;
+
+    // Return the length of the list, or 0 if the key does not exist.
+    // `ErrorKind::WrongType` is returned if the key is not a list.
+    fn llen(&self, key: K) -> Result<usize> {
+        Ok(op::as_list(self.get(key))?.map_or(0, |list| list.len()))
+    }
+
+    // Return the items of the list in [start, stop]. Negative indexes
+    // count from the tail, so -1 is the last item.
+    fn lrange(&self, key: K, start: i64, stop: i64) -> Result<Vec<Data>> {
+        let list = match op::as_list(self.get(key))? {
+            Some(list) => list,
+            None => return Ok(Vec::new()),
+        };
+        Ok(match op::range_of(start, stop, list.len()) {
+               Some((start, stop)) => list.range(start..stop + 1).cloned().collect(),
+               None => Vec::new(),
+           })
+    }
+
+    // Return the value of the field of the hash, or `None` if the key
+    // or the field does not exist. `ErrorKind::WrongType` is returned
+    // if the key is not a hash.
+    fn hget(&self, key: K, field: &str) -> Result<Option<&Data>> {
+        Ok(op::as_hash(self.get(key))?.and_then(|hash| hash.get(field)))
+    }
+
+    // Return all the fields of the hash with their values.
+    fn hgetall(&self, key: K) -> Result<BTreeMap<String, Data>> {
+        Ok(op::as_hash(self.get(key))?.cloned().unwrap_or_default())
+    }
+
+    fn hexists(&self, key: K, field: &str) -> Result<bool> {
+        Ok(ReadTransaction::hget(self, key, field)?.is_some())
+    }
+
+    // Return all the fields of the hash, in order.
+    fn hkeys(&self, key: K) -> Result<Vec<String>> {
+        Ok(op::as_hash(self.get(key))?.map_or_else(Vec::new, |hash| hash.keys().cloned().collect()))
+    }
+
+    // Return whether the member is in the set. `ErrorKind::WrongType`
+    // is returned if the key is not a set.
+    fn sismember(&self, key: K, member: &str) -> Result<bool> {
+        Ok(op::as_set(self.get(key))?.is_some_and(|set| set.contains(member)))
+    }
+
+    fn smembers(&self, key: K) -> Result<BTreeSet<String>> {
+        Ok(op::as_set(self.get(key))?.cloned().unwrap_or_default())
+    }
+
+    // Return the members which are in all the sets, a key which does
+    // not exist is an empty set.
+    fn sinter(&self, keys: &[K]) -> Result<BTreeSet<String>> {
+        let mut members: Option<BTreeSet<String>> = None;
+        for key in keys {
+            let set = op::as_set(self.get(key.clone()))?;
+            members = Some(match (members, set) {
+                               (None, set) => set.cloned().unwrap_or_default(),
+                               (Some(mut members), Some(set)) => {
+                                   members.retain(|member| set.contains(member));
+                                   members
+                               }
+                               (Some(_), None) => BTreeSet::new(),
+                           });
+        }
+        Ok(members.unwrap_or_default())
+    }
+
+    // Return the members which are in any of the sets.
+    fn sunion(&self, keys: &[K]) -> Result<BTreeSet<String>> {
+        let mut members = BTreeSet::new();
+        for key in keys {
+            if let Some(set) = op::as_set(self.get(key.clone()))? {
+                members.extend(set.iter().cloned());
+            }
+        }
+        Ok(members)
+    }
+
+    // Return the score of the member of the sorted set.
+    // `ErrorKind::WrongType` is returned if the key is not a sorted
+    // set.
+    fn zscore(&self, key: K, member: &str) -> Result<Option<f64>> {
+        Ok(op::as_sorted_set(self.get(key))?.and_then(|set| set.score(member)))
+    }
+
+    // Return the members whose score is in [min, max] with their
+    // scores, ordered by score.
+    fn zrange_by_score(&self, key: K, min: f64, max: f64) -> Result<Vec<(String, f64)>> {
+        Ok(match op::as_sorted_set(self.get(key))? {
+               Some(set) => {
+                   set.range_by_score(min, max)
+                       .map(|(member, score)| (member.clone(), score))
+                       .collect()
+               }
+               None => Vec::new(),
+           })
+    }
+
+    // Return the position of the member ordered by score, starting
+    // from 0.
+    fn zrank(&self, key: K, member: &str) -> Result<Option<usize>> {
+        Ok(op::as_sorted_set(self.get(key))?.and_then(|set| set.rank(member)))
+    }
 }
 
 // An update transaction on the dasebase.
 pub trait WriteTransaction<K>: ReadTransaction<K>
     where K: Into<String> + Ord + Clone
 {
+    // Set the value of the key, which lives forever.
     fn update(&mut self, key: K, value: Data) -> Option<Data>;
+    // Set the value of the key, which is expired after the duration.
+    fn update_with_ttl(&mut self, key: K, value: Data, ttl: Duration) -> Option<Data>;
+    // Let the key be expired after the duration, return false if the
+    // key does not exist.
+    fn expire(&mut self, key: K, ttl: Duration) -> bool;
+    // Let the key live forever, return false if the key does not exist
+    // or already lives forever.
+    fn persist(&mut self, key: K) -> bool;
     fn remove(&mut self, key: K) -> Option<Data>;
     fn clear(&mut self);
+    // Set the value of the key if it is as expected, and return the
+    // new version. Otherwise `ErrorKind::Conflict` is returned and the
+    // key is not changed.
+    fn compare_and_swap(&mut self, key: K, expected: Expected, value: Data) -> Result<u64>;
+    // Remove the key if it is as expected, and return its value.
+    // Otherwise `ErrorKind::Conflict` is returned and the key is not
+    // removed.
+    fn compare_and_remove(&mut self, key: K, expected: Expected) -> Result<Data>;
+    // Push the value to the head of the list, which is created if the
+    // key does not exist, and return the new length of the list.
+    // `ErrorKind::WrongType` is returned if the key is not a list.
+    fn lpush(&mut self, key: K, value: Data) -> Result<usize>;
+    // Push the value to the tail of the list.
+    fn rpush(&mut self, key: K, value: Data) -> Result<usize>;
+    // Pop the head of the list, the key is removed with its last item.
+    fn lpop(&mut self, key: K) -> Result<Option<Data>>;
+    // Pop the tail of the list.
+    fn rpop(&mut self, key: K) -> Result<Option<Data>>;
+    // Remove the first `count` items equal to the value from the head
+    // of the list, or from the tail if `count` is negative, or all of
+    // them if it is 0. Return how many items are removed.
+    fn lrem(&mut self, key: K, count: i64, value: Data) -> Result<usize>;
+    // Only keep the items of the list in [start, stop], which are
+    // counted like `lrange`.
+    fn ltrim(&mut self, key: K, start: i64, stop: i64) -> Result<()>;
+    // Set the value of the field of the hash, which is created if the
+    // key does not exist, and return the previous value of the field.
+    // `ErrorKind::WrongType` is returned if the key is not a hash.
+    fn hset(&mut self, key: K, field: &str, value: Data) -> Result<Option<Data>>;
+    // Remove the field from the hash, the key is removed with its last
+    // field.
+    fn hdel(&mut self, key: K, field: &str) -> Result<Option<Data>>;
+    // Add the delta to the integer of the field, which starts from 0
+    // if it does not exist, and return the result.
+    // `ErrorKind::WrongType` is returned if the field is not an
+    // integer, and `ErrorKind::Overflow` if the result overflows.
+    fn hincrby(&mut self, key: K, field: &str, delta: i64) -> Result<i64>;
+    // Add the member to the set, which is created if the key does not
+    // exist, and return false if it is already in the set.
+    // `ErrorKind::WrongType` is returned if the key is not a set.
+    fn sadd(&mut self, key: K, member: &str) -> Result<bool>;
+    // Remove the member from the set, and return false if it is not in
+    // the set. The key is removed with its last member.
+    fn srem(&mut self, key: K, member: &str) -> Result<bool>;
+    // Set the score of the member of the sorted set, which is created
+    // if the key does not exist, and return its previous score.
+    // `ErrorKind::WrongType` is returned if the key is not a sorted
+    // set, and `ErrorKind::InvalidScore` if the score is NaN.
+    fn zadd(&mut self, key: K, member: &str, score: f64) -> Result<Option<f64>>;
+    // Remove the member from the sorted set, and return its score. The
+    // key is removed with its last member.
+    fn zrem(&mut self, key: K, member: &str) -> Result<Option<f64>>;
+    // Remove the member with the lowest score, and return it with its
+    // score.
+    fn zpopmin(&mut self, key: K) -> Result<Option<(String, f64)>>;
+    // Add the delta to the integer of the key, which starts from 0 if
+    // the key does not exist, and return the result. The key keeps its
+    // time to live. `ErrorKind::WrongType` is returned if the key is
+    // not an integer, and `ErrorKind::Overflow` if the result
+    // overflows.
+    fn incr_by(&mut self, key: K, delta: i64) -> Result<i64>;
+    // Subtract the delta from the integer of the key, like `incr_by`.
+    fn decr_by(&mut self, key: K, delta: i64) -> Result<i64>;
+    // Add the delta to the float or the integer of the key, which
+    // becomes a float. `ErrorKind::Overflow` is returned if the result
+    // is not finite.
+    fn incr_by_float(&mut self, key: K, delta: f64) -> Result<f64>;
+    // Mark the current point of the transaction.
+    fn savepoint(&mut self) -> Savepoint;
+    // Undo the changes made after the savepoint. The savepoint could
+    // be rolled back to again.
+    fn rollback_to(&mut self, savepoint: &Savepoint);
+    // Forget the savepoint, its changes are kept in the transaction.
+    fn release(&mut self, savepoint: Savepoint);
 }
 
+// The writer of the database. The store is shared with the snapshots
+// taken by the readers, and it is copied on the first change after a
+// snapshot is taken, so the snapshots are never changed. The copy is
+// cheap since the copied store shares its items with the snapshot, and
+// an item is only copied when it is changed.
 pub struct Transaction {
-    store: BTreeMap<String, Data>,
-    persist_store: RefCell<Box<Persistable>>,
-    backup_store: Option<BTreeMap<String, Data>>,
-    items_to_sync: Vec<Item>,
-    rollback_items: Vec<Item>,
+    store: Arc<Store>,
+    // The version of the last change.
+    version: u64,
+    persist_store: Box<dyn Persistable>,
+    // Items changed by the current transaction.
+    items_to_sync: Vec<SyncItem>,
+    // Items of the committed transactions which are not saved yet,
+    // with the ids of the transactions.
+    committed_items: Vec<(u64, Vec<SyncItem>)>,
+    // The id of the last committed transaction.
+    last_id: u64,
+    // Whether the committed transactions are queued to be saved. They
+    // are not if they are never saved, such as in memory.
+    save_commits: bool,
+    // How to undo the changes of the current transaction, in order.
+    rollback_items: Vec<RollbackItem>,
 }
 
 impl Transaction {
-    pub fn new(store: BTreeMap<String, Data>, persist: Box<Persistable>) -> Transaction {
+    pub fn new(store: Store, persist: Box<dyn Persistable>, save_commits: bool) -> Transaction {
+        let version = store.base_version();
         Transaction {
-            store: store,
-            backup_store: None,
-            persist_store: RefCell::new(persist),
+            version,
+            store: Arc::new(store),
+            persist_store: persist,
             items_to_sync: Vec::new(),
+            committed_items: Vec::new(),
+            last_id: version,
+            save_commits,
             rollback_items: Vec::new(),
         }
     }
 
-    pub fn save(&self) -> Result<()> {
-        for item in &self.items_to_sync {
-            match item.value {
-                Some(ref value) => {
-                    self.persist_store
-                        .borrow_mut()
-                        .set(item.key.clone(), value.clone())?
-                }
-                None => {
-                    self.persist_store
-                        .borrow_mut()
-                        .remove(item.key.clone())?
-                }
-            }
+    // Return the current store, which is not changed by the following
+    // transactions.
+    pub fn snapshot(&self) -> Arc<Store> {
+        self.store.clone()
+    }
+
+    // Write all the committed but not saved items to the persist store.
+    // A transaction is only dropped from the queue once its commit mark
+    // is written, so the ones left after an error are saved again by
+    // the next call.
+    pub fn save(&mut self) -> Result<()> {
+        while let Some(&(id, ref items)) = self.committed_items.first() {
+            save_items(&mut *self.persist_store, items, id)?;
+            self.committed_items.remove(0);
         }
 
         Ok(())
     }
 
+    // Flush the saved items of the persist store to the disk.
+    pub fn sync(&mut self) -> Result<()> {
+        self.persist_store.sync()
+    }
+
+    // Release the lock of the persist store.
+    pub fn close(&mut self) -> Result<()> {
+        self.persist_store.close()
+    }
+
+    // Rewrite the persist store from the current store. The committed
+    // but not saved items are dropped since they are already in the
+    // current store.
+    pub fn compact(&mut self) -> Result<()> {
+        self.persist_store.compact(&self.store)?;
+        self.committed_items.clear();
+        Ok(())
+    }
+
+    // Dump the current store to a snapshot of the persist store. The
+    // committed but not saved items are dropped like `compact`.
+    pub fn checkpoint(&mut self) -> Result<()> {
+        self.persist_store.checkpoint(&self.store)?;
+        self.committed_items.clear();
+        Ok(())
+    }
+
+    // Return whether the persist store should be checkpointed
+    // recording to the checkpoint policy.
+    pub fn should_checkpoint(&self, policy: CheckpointPolicy) -> Result<bool> {
+        match policy {
+            CheckpointPolicy::Never => Ok(false),
+            CheckpointPolicy::Auto { log_size } => {
+                Ok(self.persist_store.log_size()? >= log_size)
+            }
+        }
+    }
+
+    // Return whether the persist store should be compacted recording
+    // to the compact policy.
+    pub fn should_compact(&self, policy: CompactPolicy) -> Result<bool> {
+        match policy {
+            CompactPolicy::Never => Ok(false),
+            CompactPolicy::Auto { min_size, ratio } => {
+                let (size, live_size) = (self.persist_store.size()?,
+                                         self.persist_store.live_size());
+                Ok(size > live_size && size >= min_size &&
+                   size as f64 >= live_size as f64 * ratio)
+            }
+        }
+    }
+
+    // Remove the expired keys in the current transaction, return how
+    // many keys are removed.
+    pub fn remove_expired(&mut self) -> usize {
+        let keys = self.store.expired_keys(store::now());
+        for key in &keys {
+            WriteTransaction::remove(self, key.clone());
+        }
+        keys.len()
+    }
+
     pub fn commit(&mut self) {
+        self.queue_items();
+        self.rollback_items.clear();
+    }
+
+    // Commit the transaction, and save it along with the ones before
+    // it. If the save fails, the transaction is rolled back and dropped
+    // from the queue, so it is neither seen nor saved later.
+    pub fn commit_and_save(&mut self) -> Result<()> {
+        let queued = self.queue_items();
+        if let Err(err) = self.save() {
+            if queued {
+                self.committed_items.pop();
+            }
+            self.rollback();
+            return Err(err);
+        }
         self.rollback_items.clear();
-        self.backup_store = None;
+        Ok(())
+    }
+
+    // Queue the items of the current transaction to be saved, return
+    // whether they are queued.
+    fn queue_items(&mut self) -> bool {
+        let items = mem::take(&mut self.items_to_sync);
+        if !self.save_commits || items.is_empty() {
+            return false;
+        }
+        // The id is the last version of the transaction, so the ids are
+        // never less than the versions. A transaction which hands out no
+        // version, such as a removal, takes a new one.
+        if self.version == self.last_id {
+            self.version += 1;
+        }
+        self.last_id = self.version;
+        self.committed_items.push((self.last_id, items));
+        true
     }
 
     pub fn rollback(&mut self) {
-        if self.is_cleared() {
-            self.store = self.backup_store.clone().unwrap();
-            self.backup_store = None;
-        }
-        for item in &self.rollback_items {
-            if item.value.is_none() {
-                self.store.remove(&item.key.clone());
-            } else {
-                self.store
-                    .insert(item.key.clone(), item.value.clone().unwrap());
+        self.rollback_to_len(0, 0);
+    }
+
+    // Undo the changes until only the given number of rollback items
+    // and items to sync are left.
+    fn rollback_to_len(&mut self, rollback_len: usize, sync_len: usize) {
+        self.items_to_sync.truncate(sync_len);
+        while self.rollback_items.len() > rollback_len {
+            match self.rollback_items.pop().unwrap() {
+                RollbackItem::Restore(key, previous) => self.store_mut().restore(key, previous),
+                RollbackItem::Clear(store) => self.store = store,
+                RollbackItem::Undo(key, op, version) => {
+                    // The inverse never fails on the value it is made
+                    // for.
+                    self.store_mut().apply(&key, op, version).ok();
+                }
             }
         }
     }
 
-    fn record_rollback_item(&mut self, key: String, value: Option<Data>) {
-        if self.backup_store.is_none() {
-            self.rollback_items.push(Item::new(key, value));
+    // Return the store to be changed, which is copied first if it is
+    // shared with a snapshot.
+    fn store_mut(&mut self) -> &mut Store {
+        Arc::make_mut(&mut self.store)
+    }
+
+    // Set the deadline of an existing key, or let it live forever.
+    fn set_deadline(&mut self, key: String, deadline: Option<u64>) {
+        self.version += 1;
+        let version = self.version;
+        let previous = self.store_mut().set_deadline(&key, deadline, version);
+        self.record_rollback_item(RollbackItem::Restore(key.clone(), previous));
+        self.record_item_to_sync(match deadline {
+                                     Some(deadline) => SyncItem::Expire(key, deadline),
+                                     None => SyncItem::Persist(key),
+                                 });
+    }
+
+    // Change the value of the key by the operation. The operation is
+    // undone by the inverse if it is given, otherwise by restoring the
+    // previous entry. An expired key is removed first.
+    fn apply(&mut self, key: String, op: Op, inverse: Option<Op>) -> Result<()> {
+        if self.store.is_expired(&key) {
+            WriteTransaction::remove(self, key.clone());
         }
+        let rollback_item = match inverse {
+            Some(inverse) => RollbackItem::Undo(key.clone(), inverse, self.store.version(&key)),
+            None => RollbackItem::Restore(key.clone(), self.store.entry(&key)),
+        };
+        self.version += 1;
+        let version = self.version;
+        self.store_mut().apply(&key, op.clone(), version)?;
+        self.record_rollback_item(rollback_item);
+        self.record_item_to_sync(SyncItem::Apply(key, op));
+        Ok(())
     }
 
-    fn record_item_to_sync(&mut self, key: String, value: Option<Data>) {
-        self.items_to_sync.push(Item::new(key, value));
+    // Push the value to the side of the list.
+    fn push(&mut self, key: String, side: Side, value: Data) -> Result<usize> {
+        let len = ReadTransaction::llen(self, key.as_str())?;
+        // A new list is undone by removing it.
+        let inverse = if len > 0 { Some(Op::Pop(side)) } else { None };
+        self.apply(key, Op::Push(side, value), inverse)?;
+        Ok(len + 1)
     }
 
-    fn is_cleared(&self) -> bool {
-        self.backup_store.is_some()
+    // Pop an item from the side of the list.
+    fn pop(&mut self, key: String, side: Side) -> Result<Option<Data>> {
+        let (item, len) = match op::as_list(self.store.get(&key))? {
+            Some(list) => {
+                let item = match side {
+                    Side::Left => list.front(),
+                    Side::Right => list.back(),
+                };
+                (item.cloned(), list.len())
+            }
+            None => return Ok(None),
+        };
+        // The last item is undone by restoring the key.
+        let inverse = match item {
+            Some(ref item) if len > 1 => Some(Op::Push(side, item.clone())),
+            _ => None,
+        };
+        self.apply(key, Op::Pop(side), inverse)?;
+        Ok(item)
+    }
+
+    // Change the integer of the key by the operation, and return the
+    // result.
+    fn change_int(&mut self, key: String, op: Op) -> Result<i64> {
+        // A number is cheap to restore, and the inverse of adding a
+        // float is not exact.
+        self.apply(key.clone(), op, None)?;
+        match self.store.get(&key) {
+            Some(&Data::Int(int)) => Ok(int),
+            _ => unreachable!(),
+        }
     }
-}
 
-impl Deref for Transaction {
-    type Target = BTreeMap<String, Data>;
-    fn deref(&self) -> &BTreeMap<String, Data> {
-        &self.store
+    // Return whether the key is as expected.
+    fn is_expected(&self, key: &str, expected: &Expected) -> bool {
+        match (self.store.get_with_version(key), expected) {
+            (None, Expected::Absent) => true,
+            (Some((_, version)), Expected::Version(expected)) => version == *expected,
+            (Some((value, _)), Expected::Value(expected)) => value == expected,
+            _ => false,
+        }
+    }
+
+    fn record_rollback_item(&mut self, item: RollbackItem) {
+        self.rollback_items.push(item);
+    }
+
+    fn record_item_to_sync(&mut self, item: SyncItem) {
+        self.items_to_sync.push(item);
     }
 }
 
@@ -123,6 +563,14 @@ impl<K> ReadTransaction<K> for Transaction
         self.store.get(&key.into())
     }
 
+    fn get_with_version(&self, key: K) -> Option<(&Data, u64)> {
+        self.store.get_with_version(&key.into())
+    }
+
+    fn ttl(&self, key: K) -> Option<Duration> {
+        self.store.ttl(&key.into())
+    }
+
     fn len(&self) -> usize {
         self.store.len()
     }
@@ -134,29 +582,468 @@ impl<K> ReadTransaction<K> for Transaction
     fn contains_key(&self, key: K) -> bool {
         self.store.contains_key(&key.into())
     }
+
+    fn iter(&self) -> Iter<'_> {
+        Iter::all(&self.store)
+    }
+
+    fn keys(&self) -> Keys<'_> {
+        Iter::all(&self.store).keys()
+    }
+
+    fn range(&self, range: Range<K>) -> Iter<'_> {
+        Iter::range(&self.store, range.start.into(), range.end.into())
+    }
+
+    fn scan_prefix(&self, prefix: K) -> Iter<'_> {
+        Iter::prefix(&self.store, prefix.into())
+    }
 }
 
 impl<K> WriteTransaction<K> for Transaction
     where K: Into<String> + Ord + Clone
 {
     fn update(&mut self, key: K, value: Data) -> Option<Data> {
-        let previous_value = self.store.insert(key.clone().into(), value.clone());
-        self.record_rollback_item(key.clone().into(), previous_value.clone());
-        self.record_item_to_sync(key.into(), Some(value));
+        self.version += 1;
+        let version = self.version;
+        let previous = self.store_mut().insert(key.clone().into(), value.clone(), version);
+        self.record_rollback_item(RollbackItem::Restore(key.clone().into(), previous.clone()));
+        self.record_item_to_sync(SyncItem::Set(key.into(), value));
+
+        previous.and_then(Entry::into_value)
+    }
+
+    fn update_with_ttl(&mut self, key: K, value: Data, ttl: Duration) -> Option<Data> {
+        let previous = WriteTransaction::update(self, key.clone(), value);
+        self.set_deadline(key.into(), Some(store::deadline_after(ttl)));
+        previous
+    }
 
-        previous_value
+    fn expire(&mut self, key: K, ttl: Duration) -> bool {
+        let key = key.into();
+        if !self.store.contains_key(&key) {
+            return false;
+        }
+        self.set_deadline(key, Some(store::deadline_after(ttl)));
+        true
+    }
+
+    fn persist(&mut self, key: K) -> bool {
+        let key = key.into();
+        if self.store.ttl(&key).is_none() {
+            return false;
+        }
+        self.set_deadline(key, None);
+        true
     }
 
     fn remove(&mut self, key: K) -> Option<Data> {
-        let previous_value = self.store.remove(&key.clone().into());
-        self.record_rollback_item(key.clone().into(), previous_value.clone());
-        self.record_item_to_sync(key.into(), None);
+        let previous = self.store_mut().remove(&key.clone().into());
+        self.record_rollback_item(RollbackItem::Restore(key.clone().into(), previous.clone()));
+        self.record_item_to_sync(SyncItem::Remove(key.into()));
 
-        previous_value
+        previous.and_then(Entry::into_value)
     }
 
     fn clear(&mut self) {
-        self.backup_store = Some(self.store.clone());
-        self.store.clear();
+        let store = mem::take(&mut self.store);
+        self.record_rollback_item(RollbackItem::Clear(store));
+        self.record_item_to_sync(SyncItem::Clear);
+    }
+
+    fn compare_and_swap(&mut self, key: K, expected: Expected, value: Data) -> Result<u64> {
+        if !self.is_expected(&key.clone().into(), &expected) {
+            return Err(Error::new(ErrorKind::Conflict));
+        }
+        WriteTransaction::update(self, key, value);
+        Ok(self.version)
+    }
+
+    fn compare_and_remove(&mut self, key: K, expected: Expected) -> Result<Data> {
+        if expected == Expected::Absent || !self.is_expected(&key.clone().into(), &expected) {
+            return Err(Error::new(ErrorKind::Conflict));
+        }
+        Ok(WriteTransaction::remove(self, key).unwrap())
+    }
+
+    fn lpush(&mut self, key: K, value: Data) -> Result<usize> {
+        self.push(key.into(), Side::Left, value)
+    }
+
+    fn rpush(&mut self, key: K, value: Data) -> Result<usize> {
+        self.push(key.into(), Side::Right, value)
+    }
+
+    fn lpop(&mut self, key: K) -> Result<Option<Data>> {
+        self.pop(key.into(), Side::Left)
+    }
+
+    fn rpop(&mut self, key: K) -> Result<Option<Data>> {
+        self.pop(key.into(), Side::Right)
+    }
+
+    fn lrem(&mut self, key: K, count: i64, value: Data) -> Result<usize> {
+        let key = key.into();
+        let matched = match op::as_list(self.store.get(&key))? {
+            Some(list) => list.iter().filter(|&item| *item == value).count(),
+            None => return Ok(0),
+        };
+        let removed = if count == 0 {
+            matched
+        } else {
+            matched.min(count.unsigned_abs() as usize)
+        };
+        if removed > 0 {
+            self.apply(key, Op::ListRemove(count, value), None)?;
+        }
+        Ok(removed)
+    }
+
+    fn ltrim(&mut self, key: K, start: i64, stop: i64) -> Result<()> {
+        let key = key.into();
+        if op::as_list(self.store.get(&key))?.is_none() {
+            return Ok(());
+        }
+        self.apply(key, Op::Trim(start, stop), None)
+    }
+
+    fn hset(&mut self, key: K, field: &str, value: Data) -> Result<Option<Data>> {
+        let key = key.into();
+        let (previous, inverse) = match op::as_hash(self.store.get(&key))? {
+            Some(hash) => {
+                let previous = hash.get(field).cloned();
+                let inverse = match previous {
+                    Some(ref previous) => Op::HashSet(String::from(field), previous.clone()),
+                    None => Op::HashRemove(String::from(field)),
+                };
+                (previous, Some(inverse))
+            }
+            // A new hash is undone by removing it.
+            None => (None, None),
+        };
+        self.apply(key, Op::HashSet(String::from(field), value), inverse)?;
+        Ok(previous)
+    }
+
+    fn hdel(&mut self, key: K, field: &str) -> Result<Option<Data>> {
+        let key = key.into();
+        let (previous, len) = match op::as_hash(self.store.get(&key))? {
+            Some(hash) => (hash.get(field).cloned(), hash.len()),
+            None => return Ok(None),
+        };
+        let previous = match previous {
+            Some(previous) => previous,
+            None => return Ok(None),
+        };
+        // The last field is undone by restoring the key.
+        let inverse = if len > 1 {
+            Some(Op::HashSet(String::from(field), previous.clone()))
+        } else {
+            None
+        };
+        self.apply(key, Op::HashRemove(String::from(field)), inverse)?;
+        Ok(Some(previous))
+    }
+
+    fn hincrby(&mut self, key: K, field: &str, delta: i64) -> Result<i64> {
+        let key = key.into();
+        let current = match ReadTransaction::hget(self, key.as_str(), field)? {
+            Some(&Data::Int(int)) => int,
+            Some(_) => return Err(Error::new(ErrorKind::WrongType)),
+            None => 0,
+        };
+        let result = match current.checked_add(delta) {
+            Some(result) => result,
+            None => return Err(Error::new(ErrorKind::Overflow)),
+        };
+        WriteTransaction::hset(self, key, field, Data::Int(result))?;
+        Ok(result)
+    }
+
+    fn sadd(&mut self, key: K, member: &str) -> Result<bool> {
+        let key = key.into();
+        let inverse = match op::as_set(self.store.get(&key))? {
+            Some(set) if set.contains(member) => return Ok(false),
+            Some(_) => Some(Op::SetRemove(String::from(member))),
+            // A new set is undone by removing it.
+            None => None,
+        };
+        self.apply(key, Op::SetAdd(String::from(member)), inverse)?;
+        Ok(true)
+    }
+
+    fn srem(&mut self, key: K, member: &str) -> Result<bool> {
+        let key = key.into();
+        let inverse = match op::as_set(self.store.get(&key))? {
+            Some(set) if set.contains(member) => {
+                // The last member is undone by restoring the key.
+                if set.len() > 1 {
+                    Some(Op::SetAdd(String::from(member)))
+                } else {
+                    None
+                }
+            }
+            _ => return Ok(false),
+        };
+        self.apply(key, Op::SetRemove(String::from(member)), inverse)?;
+        Ok(true)
+    }
+
+    fn zadd(&mut self, key: K, member: &str, score: f64) -> Result<Option<f64>> {
+        // NaN has no place in the order of the scores.
+        if score.is_nan() {
+            return Err(Error::new(ErrorKind::InvalidScore));
+        }
+        let key = key.into();
+        let (previous, inverse) = match op::as_sorted_set(self.store.get(&key))? {
+            Some(set) => {
+                let previous = set.score(member);
+                let inverse = match previous {
+                    Some(previous) => Op::SortedAdd(String::from(member), previous),
+                    None => Op::SortedRemove(String::from(member)),
+                };
+                (previous, Some(inverse))
+            }
+            // A new sorted set is undone by removing it.
+            None => (None, None),
+        };
+        self.apply(key, Op::SortedAdd(String::from(member), score), inverse)?;
+        Ok(previous)
+    }
+
+    fn zrem(&mut self, key: K, member: &str) -> Result<Option<f64>> {
+        let key = key.into();
+        let (score, len) = match op::as_sorted_set(self.store.get(&key))? {
+            Some(set) => {
+                match set.score(member) {
+                    Some(score) => (score, set.len()),
+                    None => return Ok(None),
+                }
+            }
+            None => return Ok(None),
+        };
+        // The last member is undone by restoring the key.
+        let inverse = if len > 1 {
+            Some(Op::SortedAdd(String::from(member), score))
+        } else {
+            None
+        };
+        self.apply(key, Op::SortedRemove(String::from(member)), inverse)?;
+        Ok(Some(score))
+    }
+
+    fn zpopmin(&mut self, key: K) -> Result<Option<(String, f64)>> {
+        let key = key.into();
+        let member = match op::as_sorted_set(self.store.get(&key))?.and_then(|set| set.first()) {
+            Some((member, _)) => member.clone(),
+            None => return Ok(None),
+        };
+        let score = WriteTransaction::zrem(self, key, &member)?;
+        Ok(score.map(|score| (member, score)))
+    }
+
+    fn incr_by(&mut self, key: K, delta: i64) -> Result<i64> {
+        self.change_int(key.into(), Op::IncrBy(delta))
+    }
+
+    fn decr_by(&mut self, key: K, delta: i64) -> Result<i64> {
+        self.change_int(key.into(), Op::DecrBy(delta))
+    }
+
+    fn incr_by_float(&mut self, key: K, delta: f64) -> Result<f64> {
+        let key = key.into();
+        self.apply(key.clone(), Op::IncrByFloat(delta), None)?;
+        match self.store.get(&key) {
+            Some(&Data::Float(float)) => Ok(float),
+            _ => unreachable!(),
+        }
+    }
+
+    fn savepoint(&mut self) -> Savepoint {
+        Savepoint {
+            rollback_len: self.rollback_items.len(),
+            sync_len: self.items_to_sync.len(),
+        }
+    }
+
+    fn rollback_to(&mut self, savepoint: &Savepoint) {
+        self.rollback_to_len(savepoint.rollback_len, savepoint.sync_len);
+    }
+
+    fn release(&mut self, _: Savepoint) {}
+}
+
+// Write the items of a committed transaction between its begin and
+// commit marks, which are given the id of the transaction.
+fn save_items(persist_store: &mut dyn Persistable, items: &[SyncItem], id: u64) -> Result<()> {
+    persist_store.begin(id)?;
+    for item in items.iter().cloned() {
+        match item {
+            SyncItem::Set(key, value) => persist_store.set(key, value)?,
+            SyncItem::Remove(key) => persist_store.remove(key)?,
+            SyncItem::Clear => persist_store.remove_all()?,
+            SyncItem::Expire(key, deadline) => persist_store.expire(key, deadline)?,
+            SyncItem::Persist(key) => persist_store.persist(key)?,
+            SyncItem::Apply(key, op) => persist_store.apply(key, op)?,
+        }
+    }
+    persist_store.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use persist::MemoryStore;
+
+    // A persist store whose commit fails while `failing` is set, and
+    // which counts the committed transactions.
+    struct FlakyStore {
+        failing: Arc<AtomicBool>,
+        commits: Arc<AtomicUsize>,
+    }
+
+    impl Persistable for FlakyStore {
+        fn set(&mut self, _: String, _: Data) -> Result<()> {
+            Ok(())
+        }
+
+        fn remove(&mut self, _: String) -> Result<()> {
+            Ok(())
+        }
+
+        fn remove_all(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn begin(&mut self, _: u64) -> Result<()> {
+            Ok(())
+        }
+
+        fn commit(&mut self) -> Result<()> {
+            if self.failing.load(Ordering::SeqCst) {
+                return Err(Error::new(ErrorKind::DataBaseClosed));
+            }
+            self.commits.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn expire(&mut self, _: String, _: u64) -> Result<()> {
+            Ok(())
+        }
+
+        fn persist(&mut self, _: String) -> Result<()> {
+            Ok(())
+        }
+
+        fn apply(&mut self, _: String, _: Op) -> Result<()> {
+            Ok(())
+        }
+
+        fn load(&mut self) -> Result<Store> {
+            Ok(Store::default())
+        }
+
+        fn clear(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn sync(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn compact(&mut self, _: &Store) -> Result<()> {
+            Ok(())
+        }
+
+        fn checkpoint(&mut self, _: &Store) -> Result<()> {
+            Ok(())
+        }
+
+        fn size(&self) -> Result<u64> {
+            Ok(0)
+        }
+
+        fn live_size(&self) -> u64 {
+            0
+        }
+
+        fn log_size(&self) -> Result<u64> {
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn test_save_retry() {
+        let (failing, commits) = (Arc::new(AtomicBool::new(true)), Arc::new(AtomicUsize::new(0)));
+        let persist_store = FlakyStore {
+            failing: failing.clone(),
+            commits: commits.clone(),
+        };
+        let mut txn = Transaction::new(Store::default(), Box::new(persist_store), true);
+        for key in &["a", "b"] {
+            WriteTransaction::update(&mut txn, *key, Data::Int(1));
+            txn.commit();
+        }
+
+        assert!(txn.save().is_err());
+        assert_eq!(2, txn.committed_items.len());
+
+        failing.store(false, Ordering::SeqCst);
+        txn.save().unwrap();
+        assert_eq!(2, commits.load(Ordering::SeqCst));
+        assert!(txn.committed_items.is_empty());
+    }
+
+    #[test]
+    fn test_commit_not_saved() {
+        let mut txn = Transaction::new(Store::default(), Box::new(MemoryStore::default()), false);
+        for _ in 0..3 {
+            WriteTransaction::update(&mut txn, "a", Data::Int(1));
+            txn.commit();
+        }
+        assert!(txn.committed_items.is_empty());
+    }
+
+    #[test]
+    fn test_commit_ids() {
+        let mut txn = Transaction::new(Store::default(), Box::new(MemoryStore::default()), true);
+        WriteTransaction::update(&mut txn, "a", Data::Int(1));
+        txn.commit();
+        WriteTransaction::remove(&mut txn, "a");
+        txn.commit();
+        WriteTransaction::<&str>::clear(&mut txn);
+        txn.commit();
+        WriteTransaction::update(&mut txn, "b", Data::Int(1));
+        txn.commit();
+
+        let ids: Vec<u64> = txn.committed_items.iter().map(|&(id, _)| id).collect();
+        assert_eq!(vec![1, 2, 3, 4], ids);
+        assert_eq!(4, txn.store.version("b"));
+    }
+
+    #[test]
+    fn test_commit_and_save_failed() {
+        let (failing, commits) = (Arc::new(AtomicBool::new(true)), Arc::new(AtomicUsize::new(0)));
+        let persist_store = FlakyStore {
+            failing: failing.clone(),
+            commits: commits.clone(),
+        };
+        let mut txn = Transaction::new(Store::default(), Box::new(persist_store), true);
+        WriteTransaction::update(&mut txn, "a", Data::Int(1));
+        assert!(txn.commit_and_save().is_err());
+        assert!(ReadTransaction::get(&txn, "a").is_none());
+        assert!(txn.committed_items.is_empty());
+
+        failing.store(false, Ordering::SeqCst);
+        WriteTransaction::update(&mut txn, "b", Data::Int(1));
+        txn.commit_and_save().unwrap();
+        assert!(ReadTransaction::get(&txn, "a").is_none());
+        assert_eq!(Some(&Data::Int(1)), ReadTransaction::get(&txn, "b"));
+        assert_eq!(1, commits.load(Ordering::SeqCst));
     }
 }
@@ -0,0 +1,97 @@
+// The helpers shared by the tests, each test only uses some of them.
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use clementine::{Database, Data, Result, Error, ErrorKind, Config, PersistType, SyncPolicy,
+                 OpenMode, WriteTransaction};
+
+pub fn get_cdb_path(name: &str) -> String {
+    let mut cdb_path = env::current_dir().unwrap();
+    cdb_path.push(String::from("tests/") + name);
+    String::from(cdb_path.as_path().to_str().unwrap())
+}
+
+// The config of a database in the file, which saves every transaction.
+pub fn file_config(path: &str, open_mode: OpenMode) -> Config {
+    Config {
+        persist_type: PersistType::File(String::from(path)),
+        sync_policy: SyncPolicy::Always,
+        open_mode,
+        ..Config::default()
+    }
+}
+
+// Remove the file of the database, along with its lock and manifest.
+pub fn remove_cdb(path: &str) {
+    for suffix in &["", ".lock", ".manifest"] {
+        let _ = fs::remove_file(format!("{}{}", path, suffix));
+    }
+}
+
+pub fn get(db: &Database, key: &'static str) -> Option<Data> {
+    db.read(|txn| Ok(txn.get(key).cloned())).unwrap()
+}
+
+// Return all the items with their versions.
+pub fn items(db: &Database) -> Vec<(String, Data, u64)> {
+    db.read(|txn| {
+                Ok(txn.keys()
+                       .map(|key| {
+                                let (data, version) = txn.get_with_version(key.clone()).unwrap();
+                                (key.clone(), data.clone(), version)
+                            })
+                       .collect())
+            })
+        .unwrap()
+}
+
+pub fn is_wrong_type<T>(result: Result<T>) -> bool {
+    matches!(result, Err(Error { kind: ErrorKind::WrongType }))
+}
+
+// Run the changes in a transaction which fails, after the items are
+// set up in another one, and check that nothing is changed, including
+// the versions. The database is returned for more checks.
+pub fn check_rollback<S, C>(setup: S, change: C) -> Database
+    where S: FnOnce(&mut dyn WriteTransaction<&'static str>) -> Result<()>,
+          C: FnOnce(&mut dyn WriteTransaction<&'static str>) -> Result<()>
+{
+    let db = Database::new(Config::default()).unwrap();
+    db.update(setup).unwrap();
+    let expected = items(&db);
+
+    let result = db.update(|txn| -> Result<()> {
+                               change(txn)?;
+                               Err(Error::new(ErrorKind::ItemNotFound))
+                           });
+    match result {
+        Err(Error { kind: ErrorKind::ItemNotFound }) => {}
+        result => panic!("unexpected result: {:?}", result),
+    }
+    assert_eq!(expected, items(&db));
+    db
+}
+
+// Write the items to the file, then check them once the database is
+// reopened, and once it is compacted and reopened again.
+pub fn check_reopen<W, C>(name: &str, write: W, check: C)
+    where W: FnOnce(&mut dyn WriteTransaction<&'static str>) -> Result<()>,
+          C: Fn(&Database)
+{
+    let path = get_cdb_path(name);
+    {
+        let db = Database::new(file_config(&path, OpenMode::Truncate)).unwrap();
+        db.update(write).unwrap();
+    }
+
+    let db = Database::new(file_config(&path, OpenMode::OpenExisting)).unwrap();
+    check(&db);
+    // The values are written as a whole by the compaction.
+    db.compact().unwrap();
+    drop(db);
+    let db = Database::new(file_config(&path, OpenMode::OpenExisting)).unwrap();
+    check(&db);
+    drop(db);
+    remove_cdb(&path);
+}
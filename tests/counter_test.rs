@@ -0,0 +1,120 @@
+extern crate clementine;
+
+mod common;
+
+use std::thread;
+use std::time::Duration;
+use clementine::{Database, Data, Result, Config, Error, ErrorKind};
+use common::*;
+
+fn is_overflow<T>(result: Result<T>) -> bool {
+    matches!(result, Err(Error { kind: ErrorKind::Overflow }))
+}
+
+#[test]
+fn test_incr_decr() {
+    let db = &Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  assert_eq!(1, txn.incr_by("a", 1)?);
+                  assert_eq!(11, txn.incr_by("a", 10)?);
+                  assert_eq!(8, txn.decr_by("a", 3)?);
+                  assert_eq!(-2, txn.decr_by("b", 2)?);
+                  txn.update("c", Data::Int(-1));
+                  assert_eq!(i64::MAX, txn.decr_by("c", i64::MIN)?);
+                  Ok(())
+              })
+        .unwrap();
+    assert_eq!(Some(Data::Int(8)), get(db, "a"));
+    assert_eq!(Some(Data::Int(-2)), get(db, "b"));
+    assert_eq!(Some(Data::Int(i64::MAX)), get(db, "c"));
+}
+
+#[test]
+fn test_incr_by_float() {
+    let db = &Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  assert_eq!(0.5, txn.incr_by_float("a", 0.5)?);
+                  assert_eq!(0.75, txn.incr_by_float("a", 0.25)?);
+                  txn.update("b", Data::Int(2));
+                  assert_eq!(1.5, txn.incr_by_float("b", -0.5)?);
+                  Ok(())
+              })
+        .unwrap();
+    assert_eq!(Some(Data::Float(0.75)), get(db, "a"));
+    assert_eq!(Some(Data::Float(1.5)), get(db, "b"));
+}
+
+#[test]
+fn test_errors() {
+    let db = &Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  txn.update("a", Data::Int(i64::MAX));
+                  txn.update("b", Data::String(String::from("1")));
+                  txn.update("c", Data::Float(f64::MAX));
+                  assert!(is_overflow(txn.incr_by("a", 1)));
+                  assert!(is_overflow(txn.decr_by("d", i64::MIN)));
+                  assert!(is_wrong_type(txn.incr_by("b", 1)));
+                  assert!(is_wrong_type(txn.incr_by("c", 1)));
+                  assert!(is_wrong_type(txn.incr_by_float("b", 1.0)));
+                  assert!(is_overflow(txn.incr_by_float("c", f64::MAX)));
+                  assert_eq!(Some(&Data::Int(i64::MAX)), txn.get("a"));
+                  assert!(!txn.contains_key("d"));
+                  Ok(())
+              })
+        .unwrap();
+}
+
+#[test]
+fn test_rollback() {
+    check_rollback(|txn| {
+                       txn.incr_by("a", 1)?;
+                       Ok(())
+                   },
+                   |txn| {
+                       txn.incr_by("a", 5)?;
+                       txn.incr_by("b", 5)?;
+                       txn.incr_by_float("a", 0.5)?;
+                       Ok(())
+                   });
+}
+
+#[test]
+fn test_expired() {
+    let db = &Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  txn.update_with_ttl("a", Data::Int(10), Duration::from_millis(10));
+                  txn.update_with_ttl("b", Data::Int(10), Duration::from_secs(60));
+                  Ok(())
+              })
+        .unwrap();
+    thread::sleep(Duration::from_millis(50));
+
+    // The expired counter starts again from 0, a live one keeps its
+    // time to live.
+    db.update(|txn| -> Result<()> {
+                  assert_eq!(1, txn.incr_by("a", 1)?);
+                  assert!(txn.ttl("a").is_none());
+                  assert_eq!(11, txn.incr_by("b", 1)?);
+                  assert!(txn.ttl("b").is_some());
+                  Ok(())
+              })
+        .unwrap();
+}
+
+#[test]
+fn test_reopen() {
+    check_reopen("test_counter_reopen.cdb",
+                 |txn| {
+                     for _ in 0..3 {
+                         txn.incr_by("a", 2)?;
+                         txn.decr_by("b", 1)?;
+                         txn.incr_by_float("c", 0.5)?;
+                     }
+                     Ok(())
+                 },
+                 |db| {
+                     assert_eq!(Some(Data::Int(6)), get(db, "a"));
+                     assert_eq!(Some(Data::Int(-3)), get(db, "b"));
+                     assert_eq!(Some(Data::Float(1.5)), get(db, "c"));
+                 });
+}
@@ -57,3 +57,35 @@ fn test_clear() {
     });
     assert!(result.is_ok());
 }
+
+#[test]
+fn test_return_value() {
+    let db = &Database::new(Config::default()).unwrap();
+    let previous = db.update(|txn| -> Result<Option<Data>> {
+                                 txn.update("1", Data::Int(1));
+                                 Ok(txn.update("1", Data::Int(2)))
+                             })
+        .unwrap();
+    assert_eq!(Some(Data::Int(1)), previous);
+
+    let value = db.read(|txn| Ok(txn.get("1").cloned())).unwrap();
+    assert_eq!(Some(Data::Int(2)), value);
+}
+
+#[test]
+fn test_move_into_transaction() {
+    let db = &Database::new(Config::default()).unwrap();
+    let name = String::from("David");
+    db.update(move |txn| -> Result<()> {
+                  txn.update("name", Data::String(name));
+                  Ok(())
+              })
+        .unwrap();
+
+    let len = db.read(|txn| {
+                          assert!(txn.contains_key("name"));
+                          Ok(txn.len())
+                      })
+        .unwrap();
+    assert_eq!(1, len);
+}
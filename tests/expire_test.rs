@@ -0,0 +1,181 @@
+extern crate clementine;
+
+mod common;
+
+use std::fs;
+use std::thread;
+use std::time::Duration;
+use clementine::{Database, Data, Result, Config, ReadTransaction, SweepPolicy, OpenMode, Error,
+                 ErrorKind};
+use common::*;
+
+#[test]
+fn test_update_with_ttl() {
+    let db = &Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  assert!(txn.update_with_ttl("a", Data::Int(1), Duration::from_millis(50))
+                              .is_none());
+                  txn.update("b", Data::Int(2));
+                  assert!(txn.ttl("a").unwrap() <= Duration::from_millis(50));
+                  assert!(txn.ttl("b").is_none());
+                  assert!(txn.ttl("c").is_none());
+                  Ok(())
+              })
+        .unwrap();
+    db.read(|txn| -> Result<()> {
+                assert_eq!(Some(&Data::Int(1)), txn.get("a"));
+                assert_eq!(2, txn.len());
+                Ok(())
+            })
+        .unwrap();
+
+    thread::sleep(Duration::from_millis(100));
+    db.read(|txn| -> Result<()> {
+                assert!(txn.get("a").is_none());
+                assert!(txn.get_with_version("a").is_none());
+                assert!(!txn.contains_key("a"));
+                assert!(txn.ttl("a").is_none());
+                assert_eq!(1, txn.len());
+                assert!(!txn.is_empty());
+                let keys: Vec<&String> = txn.keys().collect();
+                assert_eq!(vec!["b"], keys);
+                Ok(())
+            })
+        .unwrap();
+
+    // An expired key is set again as a new one.
+    db.update(|txn| -> Result<()> {
+                  assert!(txn.update("a", Data::Int(3)).is_none());
+                  assert!(txn.ttl("a").is_none());
+                  Ok(())
+              })
+        .unwrap();
+    db.read(|txn| -> Result<()> {
+                assert_eq!(Some(&Data::Int(3)), txn.get("a"));
+                Ok(())
+            })
+        .unwrap();
+}
+
+#[test]
+fn test_expire_and_persist() {
+    let db = &Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  assert!(!txn.expire("a", Duration::from_secs(60)));
+                  assert!(!txn.persist("a"));
+                  txn.update("a", Data::Int(1));
+                  assert!(!txn.persist("a"));
+                  assert!(txn.expire("a", Duration::from_secs(60)));
+                  assert!(txn.ttl("a").unwrap() > Duration::from_secs(59));
+                  Ok(())
+              })
+        .unwrap();
+
+    db.update(|txn| -> Result<()> {
+                  assert!(txn.persist("a"));
+                  assert!(txn.ttl("a").is_none());
+                  assert!(txn.expire("a", Duration::from_millis(0)));
+                  assert!(txn.get("a").is_none());
+                  assert!(!txn.expire("a", Duration::from_secs(60)));
+                  Ok(())
+              })
+        .unwrap();
+    assert!(db.read(|txn| Ok(txn.get("a").is_none())).unwrap());
+}
+
+#[test]
+fn test_rollback_ttl() {
+    let db = &Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  txn.update_with_ttl("a", Data::Int(1), Duration::from_secs(60));
+                  txn.update("b", Data::Int(2));
+                  Ok(())
+              })
+        .unwrap();
+
+    let result = db.update(|txn| -> Result<()> {
+                               txn.persist("a");
+                               txn.expire("b", Duration::from_millis(0));
+                               Err(Error::new(ErrorKind::ItemNotFound))
+                           });
+    assert!(result.is_err());
+    db.read(|txn| -> Result<()> {
+                assert!(txn.ttl("a").is_some());
+                assert!(txn.ttl("b").is_none());
+                assert_eq!(Some(&Data::Int(2)), txn.get("b"));
+                Ok(())
+            })
+        .unwrap();
+}
+
+#[test]
+fn test_ttl_persisted() {
+    let path = get_cdb_path("test_ttl_persisted.cdb");
+    let config = |open_mode| file_config(&path, open_mode);
+    {
+        let db = Database::new(config(OpenMode::Truncate)).unwrap();
+        db.update(|txn| -> Result<()> {
+                      txn.update_with_ttl("short", Data::Int(1), Duration::from_millis(50));
+                      txn.update_with_ttl("long", Data::Int(2), Duration::from_secs(60));
+                      txn.update_with_ttl("persisted", Data::Int(3), Duration::from_millis(50));
+                      txn.persist("persisted");
+                      Ok(())
+                  })
+            .unwrap();
+    }
+    thread::sleep(Duration::from_millis(100));
+
+    let check = |db: &Database| {
+        db.read(|txn| -> Result<()> {
+                    assert!(txn.get("short").is_none());
+                    assert_eq!(Some(&Data::Int(2)), txn.get("long"));
+                    assert!(txn.ttl("long").unwrap() > Duration::from_secs(50));
+                    assert_eq!(Some(&Data::Int(3)), txn.get("persisted"));
+                    assert!(txn.ttl("persisted").is_none());
+                    assert_eq!(2, txn.len());
+                    Ok(())
+                })
+            .unwrap();
+    };
+    {
+        let db = Database::new(config(OpenMode::OpenExisting)).unwrap();
+        check(&db);
+        // The deadlines are kept by the compaction.
+        db.compact().unwrap();
+    }
+    let db = Database::new(config(OpenMode::OpenExisting)).unwrap();
+    check(&db);
+    drop(db);
+    remove_cdb(&path);
+}
+
+#[test]
+fn test_sweep_persisted() {
+    let path = get_cdb_path("test_sweep_persisted.cdb");
+    let config = |open_mode| {
+        Config {
+            sweep_policy: SweepPolicy::Every(Duration::from_millis(10)),
+            ..file_config(&path, open_mode)
+        }
+    };
+    let size = {
+        let db = Database::new(config(OpenMode::Truncate)).unwrap();
+        db.update(|txn| -> Result<()> {
+                      txn.update_with_ttl("a", Data::Int(1), Duration::from_millis(10));
+                      Ok(())
+                  })
+            .unwrap();
+        let size = fs::metadata(&path).unwrap().len();
+        thread::sleep(Duration::from_millis(100));
+        size
+    };
+
+    // The removal of the expired key is appended to the file.
+    assert!(fs::metadata(&path).unwrap().len() > size);
+    let db = Database::new(config(OpenMode::OpenExisting)).unwrap();
+    let txn = db.begin_read().unwrap();
+    assert!(ReadTransaction::<&str>::is_empty(&txn));
+    drop(txn);
+    drop(db);
+    remove_cdb(&path);
+}
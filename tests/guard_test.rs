@@ -0,0 +1,152 @@
+extern crate clementine;
+
+mod common;
+
+use clementine::{Database, Data, Config, ReadTransaction, WriteTransaction, WriteGuard, OpenMode,
+                 Error, ErrorKind};
+use common::*;
+
+fn transfer(txn: &mut WriteGuard, from: &str, to: &str, amount: i64) -> bool {
+    let balance = match txn.get(from) {
+        Some(&Data::Int(balance)) => balance,
+        _ => return false,
+    };
+    if balance < amount {
+        return false;
+    }
+    txn.update(from, Data::Int(balance - amount));
+    txn.update(to, Data::Int(amount));
+    true
+}
+
+#[test]
+fn test_commit() {
+    let db = Database::new(Config::default()).unwrap();
+    let mut txn = db.begin_write().unwrap();
+    txn.update("a", Data::Int(10));
+    assert!(transfer(&mut txn, "a", "b", 3));
+    txn.commit().unwrap();
+
+    let txn = db.begin_read().unwrap();
+    assert_eq!(Some(&Data::Int(7)), txn.get("a"));
+    assert_eq!(Some(&Data::Int(3)), txn.get("b"));
+    assert_eq!(2, ReadTransaction::<&str>::len(&txn));
+}
+
+#[test]
+fn test_rollback() {
+    let db = Database::new(Config::default()).unwrap();
+    let mut txn = db.begin_write().unwrap();
+    txn.update("a", Data::Int(1));
+    txn.commit().unwrap();
+
+    let mut txn = db.begin_write().unwrap();
+    txn.update("a", Data::Int(2));
+    WriteTransaction::<&str>::clear(&mut txn);
+    txn.rollback();
+
+    let txn = db.begin_read().unwrap();
+    assert_eq!(Some(&Data::Int(1)), txn.get("a"));
+}
+
+#[test]
+fn test_read_commit_rollback() {
+    let db = Database::new(Config::default()).unwrap();
+    let mut txn = db.begin_write().unwrap();
+    txn.update("a", Data::Int(1));
+    txn.commit().unwrap();
+
+    let txn = db.begin_read().unwrap();
+    assert_eq!(Some(&Data::Int(1)), txn.get("a"));
+    txn.commit().unwrap();
+    let txn = db.begin_read().unwrap();
+    assert_eq!(Some(&Data::Int(1)), txn.get("a"));
+    txn.rollback();
+}
+
+#[test]
+fn test_rollback_on_drop() {
+    let db = Database::new(Config::default()).unwrap();
+    {
+        let mut txn = db.begin_write().unwrap();
+        txn.update("a", Data::Int(1));
+        assert!(!transfer(&mut txn, "a", "b", 2));
+    }
+
+    let txn = db.begin_read().unwrap();
+    assert!(txn.get("a").is_none());
+    assert!(txn.get("b").is_none());
+}
+
+#[test]
+fn test_commit_persisted() {
+    let path = get_cdb_path("test_guard_commit_persisted.cdb");
+    let config = |open_mode| file_config(&path, open_mode);
+    {
+        let db = Database::new(config(OpenMode::Truncate)).unwrap();
+        let mut txn = db.begin_write().unwrap();
+        txn.update("a", Data::Int(1));
+        txn.commit().unwrap();
+        let mut txn = db.begin_write().unwrap();
+        txn.update("b", Data::Int(2));
+    }
+
+    let db = Database::new(config(OpenMode::OpenExisting)).unwrap();
+    let txn = db.begin_read().unwrap();
+    assert_eq!(Some(&Data::Int(1)), txn.get("a"));
+    assert!(txn.get("b").is_none());
+    drop(txn);
+    drop(db);
+    remove_cdb(&path);
+}
+
+#[test]
+fn test_begin_closed() {
+    let mut db = Database::new(Config::default()).unwrap();
+    db.close().unwrap();
+    match db.begin_write() {
+        Err(Error { kind: ErrorKind::DataBaseClosed }) => {}
+        _ => panic!("the database should be closed"),
+    }
+    assert!(db.begin_read().is_err());
+}
+
+#[test]
+fn test_read_while_writing() {
+    let db = Database::new(Config::default()).unwrap();
+    let mut txn = db.begin_write().unwrap();
+    txn.update("a", Data::Int(1));
+    txn.commit().unwrap();
+
+    let before = db.begin_read().unwrap();
+    let mut txn = db.begin_write().unwrap();
+    txn.update("a", Data::Int(2));
+    txn.update("b", Data::Int(3));
+
+    // The changes are not seen until committed.
+    let during = db.begin_read().unwrap();
+    assert_eq!(Some(&Data::Int(1)), during.get("a"));
+    assert!(during.get("b").is_none());
+    txn.commit().unwrap();
+
+    // The earlier read transactions keep their snapshots.
+    assert_eq!(Some(&Data::Int(1)), before.get("a"));
+    assert_eq!(1, ReadTransaction::<&str>::len(&during));
+    let after = db.begin_read().unwrap();
+    assert_eq!(Some(&Data::Int(2)), after.get("a"));
+    assert_eq!(Some(&Data::Int(3)), after.get("b"));
+}
+
+#[test]
+fn test_read_in_update() {
+    let db = Database::new(Config::default()).unwrap();
+    db.update::<Error, _, _, _>(|txn| {
+            txn.update("a", Data::Int(1));
+            let value = db.read(|txn| Ok(txn.get("a").cloned()))?;
+            assert!(value.is_none());
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(Some(Data::Int(1)),
+               db.read(|txn| Ok(txn.get("a").cloned())).unwrap());
+}
@@ -0,0 +1,145 @@
+extern crate clementine;
+
+mod common;
+
+use std::collections::BTreeMap;
+use clementine::{Database, Data, Result, Config, Error, ErrorKind};
+use common::*;
+
+fn hash(fields: &[(&str, i64)]) -> BTreeMap<String, Data> {
+    fields
+        .iter()
+        .map(|&(field, value)| (String::from(field), Data::Int(value)))
+        .collect()
+}
+
+fn getall(db: &Database, key: &'static str) -> BTreeMap<String, Data> {
+    db.read(|txn| txn.hgetall(key)).unwrap()
+}
+
+#[test]
+fn test_set_get() {
+    let db = &Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  assert_eq!(None, txn.hset("a", "x", Data::Int(1))?);
+                  assert_eq!(None, txn.hset("a", "y", Data::Int(2))?);
+                  assert_eq!(Some(Data::Int(1)), txn.hset("a", "x", Data::Int(3))?);
+                  assert_eq!(Some(&Data::Int(3)), txn.hget("a", "x")?);
+                  assert_eq!(None, txn.hget("a", "z")?);
+                  assert_eq!(None, txn.hget("b", "x")?);
+                  Ok(())
+              })
+        .unwrap();
+    db.read(|txn| -> Result<()> {
+                assert!(txn.hexists("a", "y")?);
+                assert!(!txn.hexists("a", "z")?);
+                assert!(!txn.hexists("b", "y")?);
+                assert_eq!(vec!["x", "y"], txn.hkeys("a")?);
+                assert!(txn.hkeys("b")?.is_empty());
+                Ok(())
+            })
+        .unwrap();
+    assert_eq!(hash(&[("x", 3), ("y", 2)]), getall(db, "a"));
+    assert!(getall(db, "b").is_empty());
+}
+
+#[test]
+fn test_del() {
+    let db = &Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  txn.hset("a", "x", Data::Int(1))?;
+                  txn.hset("a", "y", Data::Int(2))?;
+                  assert_eq!(Some(Data::Int(1)), txn.hdel("a", "x")?);
+                  assert_eq!(None, txn.hdel("a", "x")?);
+                  assert_eq!(None, txn.hdel("b", "x")?);
+                  assert_eq!(Some(Data::Int(2)), txn.hdel("a", "y")?);
+                  assert!(!txn.contains_key("a"));
+                  Ok(())
+              })
+        .unwrap();
+}
+
+#[test]
+fn test_incrby() {
+    let db = &Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  assert_eq!(5, txn.hincrby("a", "x", 5)?);
+                  assert_eq!(2, txn.hincrby("a", "x", -3)?);
+                  txn.hset("a", "y", Data::Int(i64::MAX))?;
+                  match txn.hincrby("a", "y", 1) {
+                      Err(Error { kind: ErrorKind::Overflow }) => {}
+                      result => panic!("unexpected result: {:?}", result),
+                  }
+                  txn.hset("a", "z", Data::String(String::from("1")))?;
+                  match txn.hincrby("a", "z", 1) {
+                      Err(Error { kind: ErrorKind::WrongType }) => {}
+                      result => panic!("unexpected result: {:?}", result),
+                  }
+                  Ok(())
+              })
+        .unwrap();
+    db.read(|txn| -> Result<()> {
+                assert_eq!(Some(&Data::Int(2)), txn.hget("a", "x")?);
+                assert_eq!(Some(&Data::Int(i64::MAX)), txn.hget("a", "y")?);
+                Ok(())
+            })
+        .unwrap();
+}
+
+#[test]
+fn test_wrong_type() {
+    let db = &Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  txn.update("a", Data::Int(1));
+                  txn.rpush("b", Data::Int(1))?;
+                  assert!(is_wrong_type(txn.hset("a", "x", Data::Int(1))));
+                  assert!(is_wrong_type(txn.hget("a", "x")));
+                  assert!(is_wrong_type(txn.hdel("b", "x")));
+                  assert!(is_wrong_type(txn.hincrby("b", "x", 1)));
+                  assert!(is_wrong_type(txn.hkeys("b")));
+                  assert!(txn.llen("c").is_ok());
+                  txn.hset("c", "x", Data::Int(1))?;
+                  assert!(is_wrong_type(txn.llen("c")));
+                  Ok(())
+              })
+        .unwrap();
+}
+
+#[test]
+fn test_rollback() {
+    check_rollback(|txn| {
+                       txn.hset("a", "x", Data::Int(1))?;
+                       txn.hset("a", "y", Data::Int(2))?;
+                       Ok(())
+                   },
+                   |txn| {
+                       txn.hset("a", "x", Data::Int(10))?;
+                       txn.hset("a", "z", Data::Int(3))?;
+                       txn.hincrby("a", "y", 5)?;
+                       txn.hdel("a", "x")?;
+                       txn.hdel("a", "y")?;
+                       txn.hdel("a", "z")?;
+                       txn.hset("b", "x", Data::Int(1))?;
+                       Ok(())
+                   });
+}
+
+#[test]
+fn test_reopen() {
+    let mut expected = hash(&[("z", 3)]);
+    expected.insert(String::from("y"), Data::String(String::from("值")));
+    check_reopen("test_hash_reopen.cdb",
+                 |txn| {
+                     txn.hset("a", "x", Data::Int(1))?;
+                     txn.hset("a", "y", Data::String(String::from("值")))?;
+                     txn.hincrby("a", "z", 3)?;
+                     txn.hdel("a", "x")?;
+                     txn.hset("b", "x", Data::Int(1))?;
+                     txn.hdel("b", "x")?;
+                     Ok(())
+                 },
+                 |db| {
+                     assert_eq!(expected, getall(db, "a"));
+                     assert!(getall(db, "b").is_empty());
+                 });
+}
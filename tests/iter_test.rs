@@ -0,0 +1,85 @@
+extern crate clementine;
+
+use clementine::{Database, Data, Result, Error, ErrorKind, Config, ReadTransaction};
+
+fn new_db() -> Database {
+    let db = Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  txn.update("user:2", Data::Int(2));
+                  txn.update("user:1", Data::Int(1));
+                  txn.update("post:1", Data::Int(10));
+                  txn.update("user:3", Data::Int(3));
+                  Ok(())
+              })
+        .unwrap();
+    db
+}
+
+#[test]
+fn test_iter() {
+    let db = new_db();
+    db.read(|txn: &dyn ReadTransaction<&str>| -> Result<()> {
+                let items: Vec<(&String, &Data)> = txn.iter().collect();
+                assert_eq!(4, items.len());
+                assert_eq!("post:1", items[0].0);
+                assert_eq!(&Data::Int(10), items[0].1);
+
+                let keys: Vec<&String> = txn.keys().rev().collect();
+                assert_eq!(vec!["user:3", "user:2", "user:1", "post:1"], keys);
+                Ok(())
+            })
+        .unwrap();
+}
+
+#[test]
+fn test_range() {
+    let db = new_db();
+    db.read(|txn| -> Result<()> {
+                let keys: Vec<&String> = txn.range("user:1".."user:3").map(|(k, _)| k).collect();
+                assert_eq!(vec!["user:1", "user:2"], keys);
+
+                let keys: Vec<&String> = txn.range("a".."user:2").rev().map(|(k, _)| k).collect();
+                assert_eq!(vec!["user:1", "post:1"], keys);
+
+                assert_eq!(0, txn.range("z".."a").count());
+                Ok(())
+            })
+        .unwrap();
+}
+
+#[test]
+fn test_scan_prefix() {
+    let db = new_db();
+    db.read(|txn| -> Result<()> {
+                let values: Vec<&Data> = txn.scan_prefix("user:").map(|(_, v)| v).collect();
+                assert_eq!(vec![&Data::Int(1), &Data::Int(2), &Data::Int(3)], values);
+
+                let keys: Vec<&String> = txn.scan_prefix("user:").rev().map(|(k, _)| k).collect();
+                assert_eq!(vec!["user:3", "user:2", "user:1"], keys);
+
+                assert_eq!(0, txn.scan_prefix("comment:").count());
+                Ok(())
+            })
+        .unwrap();
+}
+
+#[test]
+fn test_iter_uncommitted() {
+    let db = new_db();
+    let result = db.update(|txn| -> Result<()> {
+                               txn.remove("user:1");
+                               txn.update("user:4", Data::Int(4));
+                               let keys: Vec<String> =
+                                   txn.scan_prefix("user:").map(|(k, _)| k.clone()).collect();
+                               assert_eq!(vec!["user:2", "user:3", "user:4"], keys);
+                               Err(Error::new(ErrorKind::ItemNotFound))
+                           });
+    assert!(result.is_err());
+
+    db.read(|txn| -> Result<()> {
+                let keys: Vec<&String> = txn.scan_prefix("user:").map(|(k, _)| k).collect();
+                assert_eq!(vec!["user:1", "user:2", "user:3"], keys);
+                Ok(())
+            })
+        .unwrap();
+}
@@ -0,0 +1,173 @@
+extern crate clementine;
+
+mod common;
+
+use std::thread;
+use std::time::Duration;
+use clementine::{Database, Data, Result, Config};
+use common::*;
+
+fn ints(items: &[i64]) -> Vec<Data> {
+    items.iter().map(|&item| Data::Int(item)).collect()
+}
+
+fn range(db: &Database, key: &'static str) -> Vec<Data> {
+    db.read(|txn| txn.lrange(key, 0, -1)).unwrap()
+}
+
+#[test]
+fn test_push_pop() {
+    let db = &Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  assert_eq!(1, txn.rpush("a", Data::Int(2))?);
+                  assert_eq!(2, txn.lpush("a", Data::Int(1))?);
+                  assert_eq!(3, txn.rpush("a", Data::Int(3))?);
+                  assert_eq!(3, txn.llen("a")?);
+                  assert_eq!(0, txn.llen("b")?);
+                  Ok(())
+              })
+        .unwrap();
+    assert_eq!(ints(&[1, 2, 3]), range(db, "a"));
+
+    db.update(|txn| -> Result<()> {
+                  assert_eq!(Some(Data::Int(1)), txn.lpop("a")?);
+                  assert_eq!(Some(Data::Int(3)), txn.rpop("a")?);
+                  assert_eq!(Some(Data::Int(2)), txn.rpop("a")?);
+                  assert_eq!(None, txn.lpop("a")?);
+                  assert!(!txn.contains_key("a"));
+                  Ok(())
+              })
+        .unwrap();
+}
+
+#[test]
+fn test_range() {
+    let db = &Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  for i in 0..5 {
+                      txn.rpush("a", Data::Int(i))?;
+                  }
+                  Ok(())
+              })
+        .unwrap();
+    db.read(|txn| -> Result<()> {
+                assert_eq!(ints(&[1, 2]), txn.lrange("a", 1, 2)?);
+                assert_eq!(ints(&[3, 4]), txn.lrange("a", -2, -1)?);
+                assert_eq!(ints(&[0, 1, 2, 3, 4]), txn.lrange("a", -10, 10)?);
+                assert!(txn.lrange("a", 3, 1)?.is_empty());
+                assert!(txn.lrange("b", 0, -1)?.is_empty());
+                Ok(())
+            })
+        .unwrap();
+}
+
+#[test]
+fn test_rem_trim() {
+    let db = &Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  for &i in &[1, 2, 1, 3, 1, 4] {
+                      txn.rpush("a", Data::Int(i))?;
+                  }
+                  assert_eq!(1, txn.lrem("a", -1, Data::Int(1))?);
+                  assert_eq!(ints(&[1, 2, 1, 3, 4]), txn.lrange("a", 0, -1)?);
+                  assert_eq!(2, txn.lrem("a", 0, Data::Int(1))?);
+                  assert_eq!(0, txn.lrem("a", 0, Data::Int(5))?);
+                  assert_eq!(0, txn.lrem("b", 0, Data::Int(1))?);
+
+                  txn.ltrim("a", 1, -1)?;
+                  assert_eq!(ints(&[3, 4]), txn.lrange("a", 0, -1)?);
+                  txn.ltrim("a", 5, 10)?;
+                  assert!(!txn.contains_key("a"));
+                  txn.ltrim("b", 0, 1)?;
+                  Ok(())
+              })
+        .unwrap();
+}
+
+#[test]
+fn test_wrong_type() {
+    let db = &Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  txn.update("a", Data::Int(1));
+                  assert!(is_wrong_type(txn.lpush("a", Data::Int(2))));
+                  assert!(is_wrong_type(txn.llen("a")));
+                  assert!(is_wrong_type(txn.lrem("a", 0, Data::Int(1))));
+                  assert!(is_wrong_type(txn.lpop("a")));
+                  assert!(is_wrong_type(txn.ltrim("a", 0, 1)));
+                  assert_eq!(Some(&Data::Int(1)), txn.get("a"));
+                  Ok(())
+              })
+        .unwrap();
+}
+
+#[test]
+fn test_rollback() {
+    let db = &check_rollback(|txn| {
+                                 txn.rpush("a", Data::Int(1))?;
+                                 txn.rpush("a", Data::Int(2))?;
+                                 Ok(())
+                             },
+                             |txn| {
+                                 txn.lpush("a", Data::Int(0))?;
+                                 txn.rpop("a")?;
+                                 txn.rpop("a")?;
+                                 txn.rpop("a")?;
+                                 txn.rpush("a", Data::Int(3))?;
+                                 txn.rpush("b", Data::Int(4))?;
+                                 Ok(())
+                             });
+
+    db.update(|txn| -> Result<()> {
+                  let savepoint = txn.savepoint();
+                  txn.lrem("a", 0, Data::Int(1))?;
+                  txn.ltrim("a", 1, 1)?;
+                  txn.rollback_to(&savepoint);
+                  Ok(())
+              })
+        .unwrap();
+    assert_eq!(ints(&[1, 2]), range(db, "a"));
+}
+
+#[test]
+fn test_push_expired() {
+    let db = &Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  txn.rpush("a", Data::Int(1))?;
+                  txn.expire("a", Duration::from_millis(10));
+                  Ok(())
+              })
+        .unwrap();
+    thread::sleep(Duration::from_millis(50));
+
+    // The expired list is not seen, a new one is created instead.
+    db.update(|txn| -> Result<()> {
+                  assert_eq!(None, txn.lpop("a")?);
+                  assert_eq!(1, txn.rpush("a", Data::Int(2))?);
+                  assert!(txn.ttl("a").is_none());
+                  Ok(())
+              })
+        .unwrap();
+    assert_eq!(ints(&[2]), range(db, "a"));
+}
+
+#[test]
+fn test_reopen() {
+    check_reopen("test_list_reopen.cdb",
+                 |txn| {
+                     for i in 0..4 {
+                         txn.rpush("a", Data::Int(i))?;
+                     }
+                     txn.lpush("a", Data::String(String::from("x")))?;
+                     txn.rpop("a")?;
+                     txn.lrem("a", 1, Data::Int(0))?;
+                     txn.ltrim("a", 0, 2)?;
+                     txn.rpush("b", Data::Int(1))?;
+                     txn.lpop("b")?;
+                     Ok(())
+                 },
+                 |db| {
+                     assert_eq!(vec![Data::String(String::from("x")), Data::Int(1), Data::Int(2)],
+                                range(db, "a"));
+                     assert!(range(db, "b").is_empty());
+                 });
+}
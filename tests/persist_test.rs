@@ -0,0 +1,525 @@
+extern crate clementine;
+
+mod common;
+
+use std::fs;
+use std::thread;
+use std::time::Duration;
+use std::fs::OpenOptions;
+use std::io::Write;
+use clementine::{Database, Data, Result, Error, ErrorKind, SyncPolicy, OpenMode, RecoveryMode,
+                 RecoveryReport, CompactPolicy, CheckpointPolicy};
+use common::*;
+
+#[test]
+fn test_reopen() {
+    let path = get_cdb_path("test_db_reopen.cdb");
+    {
+        let db = Database::new(file_config(&path, OpenMode::Truncate)).unwrap();
+        db.update(|txn| -> Result<()> {
+                      txn.update("1", Data::Int(1));
+                      txn.update("2", Data::String(String::from("2")));
+                      Ok(())
+                  })
+            .unwrap();
+    }
+    {
+        let db = Database::new(file_config(&path, OpenMode::OpenExisting)).unwrap();
+        db.update(|txn| -> Result<()> {
+                      assert_eq!(&Data::Int(1), txn.get("1").unwrap());
+                      txn.remove("2");
+                      txn.update("3", Data::Int(3));
+                      Ok(())
+                  })
+            .unwrap();
+    }
+
+    let db = Database::new(file_config(&path, OpenMode::OpenOrCreate)).unwrap();
+    db.read(|txn| -> Result<()> {
+                assert_eq!(&Data::Int(1), txn.get("1").unwrap());
+                assert_eq!(2, txn.len());
+                assert!(txn.get("2").is_none());
+                assert_eq!(&Data::Int(3), txn.get("3").unwrap());
+                Ok(())
+            })
+        .unwrap();
+    remove_cdb(&path);
+}
+
+#[test]
+fn test_reopen_non_ascii() {
+    let path = get_cdb_path("test_db_reopen_non_ascii.cdb");
+    {
+        let db = Database::new(file_config(&path, OpenMode::Truncate)).unwrap();
+        db.update(|txn| -> Result<()> {
+                      txn.update("键", Data::String(String::from("值")));
+                      txn.update("ключ", Data::String(String::from("значение")));
+                      Ok(())
+                  })
+            .unwrap();
+    }
+
+    let db = Database::new(file_config(&path, OpenMode::OpenExisting)).unwrap();
+    db.read(|txn| -> Result<()> {
+                assert_eq!(&Data::String(String::from("值")), txn.get("键").unwrap());
+                assert_eq!(&Data::String(String::from("значение")),
+                           txn.get("ключ").unwrap());
+                Ok(())
+            })
+        .unwrap();
+    remove_cdb(&path);
+}
+
+#[test]
+fn test_reopen_data_types() {
+    let path = get_cdb_path("test_db_reopen_data_types.cdb");
+    let items = vec![("bytes", Data::Bytes(vec![0, 0xff, b'\r', b'\n', b'$'])),
+                     ("empty", Data::Bytes(Vec::new())),
+                     ("float", Data::Float(-1.25)),
+                     ("true", Data::Bool(true)),
+                     ("false", Data::Bool(false)),
+                     ("null", Data::Null)];
+    let check = |db: &Database| {
+        for &(key, ref value) in &items {
+            let stored = db.read(|txn| Ok(txn.get(key).cloned())).unwrap();
+            assert_eq!(Some(value.clone()), stored);
+        }
+    };
+    {
+        let db = Database::new(file_config(&path, OpenMode::Truncate)).unwrap();
+        db.update(|txn| -> Result<()> {
+                      for &(key, ref value) in &items {
+                          txn.update(key, value.clone());
+                      }
+                      Ok(())
+                  })
+            .unwrap();
+    }
+    {
+        let db = Database::new(file_config(&path, OpenMode::OpenExisting)).unwrap();
+        check(&db);
+        db.compact().unwrap();
+    }
+
+    let db = Database::new(file_config(&path, OpenMode::OpenExisting)).unwrap();
+    check(&db);
+    drop(db);
+    remove_cdb(&path);
+}
+
+#[test]
+fn test_reopen_truncate() {
+    let path = get_cdb_path("test_db_reopen_truncate.cdb");
+    {
+        let db = Database::new(file_config(&path, OpenMode::Truncate)).unwrap();
+        db.update(|txn| -> Result<()> {
+                      txn.update("1", Data::Int(1));
+                      Ok(())
+                  })
+            .unwrap();
+    }
+
+    let db = Database::new(file_config(&path, OpenMode::Truncate)).unwrap();
+    db.read(|txn| -> Result<()> {
+                assert!(txn.get("1").is_none());
+                assert!(txn.is_empty());
+                Ok(())
+            })
+        .unwrap();
+    remove_cdb(&path);
+}
+
+#[test]
+fn test_open_missing() {
+    let path = get_cdb_path("test_db_open_missing.cdb");
+    remove_cdb(&path);
+    assert!(Database::new(file_config(&path, OpenMode::OpenExisting)).is_err());
+    remove_cdb(&path);
+}
+
+#[test]
+fn test_sync_every() {
+    let path = get_cdb_path("test_db_sync_every.cdb");
+    let mut config = file_config(&path, OpenMode::Truncate);
+    config.sync_policy = SyncPolicy::Every(Duration::from_secs(6));
+    let db = Database::new(config).unwrap();
+    db.update(|txn| -> Result<()> {
+                  txn.update("1", Data::Int(1));
+                  Ok(())
+              })
+        .unwrap();
+    assert_eq!(0, fs::metadata(&path).unwrap().len());
+
+    thread::sleep(Duration::from_millis(6500));
+    assert!(db.flushes() >= 1);
+    assert!(fs::metadata(&path).unwrap().len() > 0);
+    drop(db);
+
+    let db = Database::new(file_config(&path, OpenMode::OpenExisting)).unwrap();
+    db.read(|txn| -> Result<()> {
+                assert_eq!(&Data::Int(1), txn.get("1").unwrap());
+                Ok(())
+            })
+        .unwrap();
+    remove_cdb(&path);
+}
+
+#[test]
+fn test_sync_every_on_close() {
+    let path = get_cdb_path("test_db_sync_every_on_close.cdb");
+    let mut config = file_config(&path, OpenMode::Truncate);
+    config.sync_policy = SyncPolicy::Every(Duration::from_secs(60));
+    {
+        let db = Database::new(config).unwrap();
+        db.update(|txn| -> Result<()> {
+                      txn.update("1", Data::Int(1));
+                      Ok(())
+                  })
+            .unwrap();
+        let result = db.update(|txn| -> Result<()> {
+                                   txn.update("2", Data::Int(2));
+                                   Err(Error::new(ErrorKind::ItemNotFound))
+                               });
+        assert!(result.is_err());
+    }
+
+    let db = Database::new(file_config(&path, OpenMode::OpenExisting)).unwrap();
+    db.read(|txn| -> Result<()> {
+                assert_eq!(&Data::Int(1), txn.get("1").unwrap());
+                assert!(txn.get("2").is_none());
+                Ok(())
+            })
+        .unwrap();
+    remove_cdb(&path);
+}
+
+#[test]
+fn test_recover_damaged_tail() {
+    let path = get_cdb_path("test_db_recover_damaged_tail.cdb");
+    {
+        let db = Database::new(file_config(&path, OpenMode::Truncate)).unwrap();
+        db.update(|txn| -> Result<()> {
+                      txn.update("1", Data::Int(1));
+                      Ok(())
+                  })
+            .unwrap();
+    }
+    OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap()
+        .write_all(&[1, 20, 0, 0, 0])
+        .unwrap();
+
+    assert!(Database::new(file_config(&path, OpenMode::OpenExisting)).is_err());
+
+    let mut config = file_config(&path, OpenMode::OpenExisting);
+    config.recovery_mode = RecoveryMode::TruncateTail;
+    {
+        let db = Database::new(config).unwrap();
+        assert_eq!(Some(&RecoveryReport {
+                             dropped_bytes: 5,
+                             dropped_records: 1,
+                         }),
+                   db.recovery_report());
+        db.update(|txn| -> Result<()> {
+                      assert_eq!(&Data::Int(1), txn.get("1").unwrap());
+                      txn.update("2", Data::Int(2));
+                      Ok(())
+                  })
+            .unwrap();
+    }
+
+    let db = Database::new(file_config(&path, OpenMode::OpenExisting)).unwrap();
+    assert!(db.recovery_report().is_none());
+    db.read(|txn| -> Result<()> {
+                assert_eq!(&Data::Int(2), txn.get("2").unwrap());
+                Ok(())
+            })
+        .unwrap();
+    remove_cdb(&path);
+}
+
+#[test]
+fn test_clear() {
+    let path = get_cdb_path("test_db_clear.cdb");
+    {
+        let db = Database::new(file_config(&path, OpenMode::Truncate)).unwrap();
+        db.update(|txn| -> Result<()> {
+                      txn.update("1", Data::Int(1));
+                      txn.update("2", Data::Int(2));
+                      Ok(())
+                  })
+            .unwrap();
+        db.update(|txn| -> Result<()> {
+                      txn.update("3", Data::Int(3));
+                      txn.clear();
+                      txn.update("4", Data::Int(4));
+                      Ok(())
+                  })
+            .unwrap();
+    }
+
+    let db = Database::new(file_config(&path, OpenMode::OpenExisting)).unwrap();
+    db.read(|txn| -> Result<()> {
+                assert_eq!(&Data::Int(4), txn.get("4").unwrap());
+                assert_eq!(1, txn.len());
+                Ok(())
+            })
+        .unwrap();
+    remove_cdb(&path);
+}
+
+#[test]
+fn test_clear_twice() {
+    let path = get_cdb_path("test_db_clear_twice.cdb");
+    {
+        let db = Database::new(file_config(&path, OpenMode::Truncate)).unwrap();
+        db.update(|txn| -> Result<()> {
+                      txn.update("1", Data::Int(1));
+                      Ok(())
+                  })
+            .unwrap();
+        let result = db.update(|txn| -> Result<()> {
+                                   txn.clear();
+                                   txn.update("2", Data::Int(2));
+                                   txn.clear();
+                                   txn.update("3", Data::Int(3));
+                                   Err(Error::new(ErrorKind::ItemNotFound))
+                               });
+        assert!(result.is_err());
+        db.update(|txn| -> Result<()> {
+                      assert_eq!(&Data::Int(1), txn.get("1").unwrap());
+                      txn.clear();
+                      txn.update("2", Data::Int(2));
+                      txn.clear();
+                      txn.update("3", Data::Int(3));
+                      Ok(())
+                  })
+            .unwrap();
+    }
+
+    let db = Database::new(file_config(&path, OpenMode::OpenExisting)).unwrap();
+    db.read(|txn| -> Result<()> {
+                assert_eq!(&Data::Int(3), txn.get("3").unwrap());
+                assert_eq!(1, txn.len());
+                Ok(())
+            })
+        .unwrap();
+    remove_cdb(&path);
+}
+
+#[test]
+fn test_compact() {
+    let path = get_cdb_path("test_db_compact.cdb");
+    {
+        let db = Database::new(file_config(&path, OpenMode::Truncate)).unwrap();
+        for i in 0..100 {
+            db.update(|txn| -> Result<()> {
+                          txn.update("1", Data::Int(i));
+                          txn.update("2", Data::Int(i));
+                          Ok(())
+                      })
+                .unwrap();
+        }
+        let size = fs::metadata(&path).unwrap().len();
+        db.compact().unwrap();
+        assert!(fs::metadata(&path).unwrap().len() < size);
+
+        db.update(|txn| -> Result<()> {
+                      txn.remove("2");
+                      Ok(())
+                  })
+            .unwrap();
+    }
+
+    let db = Database::new(file_config(&path, OpenMode::OpenExisting)).unwrap();
+    db.read(|txn| -> Result<()> {
+                assert_eq!(&Data::Int(99), txn.get("1").unwrap());
+                assert_eq!(1, txn.len());
+                Ok(())
+            })
+        .unwrap();
+    remove_cdb(&path);
+}
+
+#[test]
+fn test_auto_compact() {
+    let path = get_cdb_path("test_db_auto_compact.cdb");
+    let mut config = file_config(&path, OpenMode::Truncate);
+    config.compact_policy = CompactPolicy::Auto {
+        min_size: 1024,
+        ratio: 2.0,
+    };
+    {
+        let db = Database::new(config).unwrap();
+        for i in 0..1000 {
+            db.update(|txn| -> Result<()> {
+                          txn.update("1", Data::Int(i));
+                          Ok(())
+                      })
+                .unwrap();
+        }
+        assert!(fs::metadata(&path).unwrap().len() < 1024);
+    }
+
+    let db = Database::new(file_config(&path, OpenMode::OpenExisting)).unwrap();
+    db.read(|txn| -> Result<()> {
+                assert_eq!(&Data::Int(999), txn.get("1").unwrap());
+                assert_eq!(1, txn.len());
+                Ok(())
+            })
+        .unwrap();
+    remove_cdb(&path);
+}
+
+#[test]
+fn test_checkpoint() {
+    let path = get_cdb_path("test_db_checkpoint.cdb");
+    {
+        let db = Database::new(file_config(&path, OpenMode::Truncate)).unwrap();
+        db.update(|txn| -> Result<()> {
+                      txn.update("1", Data::Int(1));
+                      txn.update("2", Data::Int(2));
+                      Ok(())
+                  })
+            .unwrap();
+        db.checkpoint().unwrap();
+        db.update(|txn| -> Result<()> {
+                      txn.remove("2");
+                      Ok(())
+                  })
+            .unwrap();
+    }
+    assert!(fs::metadata(format!("{}.manifest", path)).is_ok());
+    assert!(fs::metadata(format!("{}.snapshot.1", path)).is_ok());
+
+    let db = Database::new(file_config(&path, OpenMode::OpenExisting)).unwrap();
+    db.read(|txn| -> Result<()> {
+                assert_eq!(&Data::Int(1), txn.get("1").unwrap());
+                assert_eq!(1, txn.len());
+                Ok(())
+            })
+        .unwrap();
+    drop(db);
+    Database::new(file_config(&path, OpenMode::Truncate)).unwrap();
+    remove_cdb(&path);
+}
+
+#[test]
+fn test_auto_checkpoint() {
+    let path = get_cdb_path("test_db_auto_checkpoint.cdb");
+    let mut config = file_config(&path, OpenMode::Truncate);
+    config.checkpoint_policy = CheckpointPolicy::Auto { log_size: 1024 };
+    {
+        let db = Database::new(config).unwrap();
+        for i in 0..100 {
+            db.update(|txn| -> Result<()> {
+                          txn.update("key", Data::Int(i));
+                          Ok(())
+                      })
+                .unwrap();
+        }
+    }
+    assert!(fs::metadata(&path).unwrap().len() < 1024);
+
+    let db = Database::new(file_config(&path, OpenMode::OpenExisting)).unwrap();
+    db.read(|txn| -> Result<()> {
+                assert_eq!(&Data::Int(99), txn.get("key").unwrap());
+                Ok(())
+            })
+        .unwrap();
+    drop(db);
+    Database::new(file_config(&path, OpenMode::Truncate)).unwrap();
+    remove_cdb(&path);
+}
+
+#[test]
+fn test_locked() {
+    let path = get_cdb_path("test_db_locked.cdb");
+    let mut db = Database::new(file_config(&path, OpenMode::Truncate)).unwrap();
+    match Database::new(file_config(&path, OpenMode::OpenExisting)) {
+        Err(Error { kind: ErrorKind::DatabaseLocked }) => {}
+        Err(err) => panic!("unexpected error: {:?}", err),
+        Ok(_) => panic!("the database should be locked"),
+    }
+
+    db.close().unwrap();
+    assert!(Database::new(file_config(&path, OpenMode::OpenExisting)).is_ok());
+    remove_cdb(&path);
+}
+
+#[test]
+fn test_read_only() {
+    let path = get_cdb_path("test_db_read_only.cdb");
+    {
+        let db = Database::new(file_config(&path, OpenMode::Truncate)).unwrap();
+        db.update(|txn| -> Result<()> {
+                      txn.update("1", Data::Int(1));
+                      Ok(())
+                  })
+            .unwrap();
+    }
+    let size = fs::metadata(&path).unwrap().len();
+
+    let mut config = file_config(&path, OpenMode::OpenExisting);
+    config.read_only = true;
+    let db = Database::new(config).unwrap();
+    let mut config = file_config(&path, OpenMode::OpenExisting);
+    config.read_only = true;
+    let other_db = Database::new(config).unwrap();
+    assert!(Database::new(file_config(&path, OpenMode::OpenExisting)).is_err());
+
+    for db in &[&db, &other_db] {
+        db.read(|txn| -> Result<()> {
+                    assert_eq!(&Data::Int(1), txn.get("1").unwrap());
+                    Ok(())
+                })
+            .unwrap();
+        let result = db.update(|txn| -> Result<()> {
+                                   txn.update("2", Data::Int(2));
+                                   Ok(())
+                               });
+        match result {
+            Err(Error { kind: ErrorKind::TransactionNotWritable }) => {}
+            _ => panic!("unexpected result: {:?}", result),
+        }
+    }
+    drop(db);
+    drop(other_db);
+    assert_eq!(size, fs::metadata(&path).unwrap().len());
+
+    let mut config = file_config(&path, OpenMode::OpenExisting);
+    config.read_only = true;
+    remove_cdb(&path);
+    assert!(Database::new(config).is_err());
+}
+
+#[test]
+fn test_savepoint() {
+    let path = get_cdb_path("test_db_savepoint.cdb");
+    {
+        let db = Database::new(file_config(&path, OpenMode::Truncate)).unwrap();
+        db.update(|txn| -> Result<()> {
+                      txn.update("1", Data::Int(1));
+                      let savepoint = txn.savepoint();
+                      txn.clear();
+                      txn.update("2", Data::Int(2));
+                      txn.rollback_to(&savepoint);
+                      txn.update("3", Data::Int(3));
+                      Ok(())
+                  })
+            .unwrap();
+    }
+
+    let db = Database::new(file_config(&path, OpenMode::OpenExisting)).unwrap();
+    db.read(|txn| -> Result<()> {
+                assert_eq!(&Data::Int(1), txn.get("1").unwrap());
+                assert!(txn.get("2").is_none());
+                assert_eq!(&Data::Int(3), txn.get("3").unwrap());
+                Ok(())
+            })
+        .unwrap();
+    drop(db);
+    remove_cdb(&path);
+}
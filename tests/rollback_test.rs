@@ -1,6 +1,8 @@
 extern crate clementine;
 
-use clementine::{Database, Data, Result, Error, ErrorKind, Config};
+use std::result;
+use std::panic::{self, AssertUnwindSafe};
+use clementine::{Database, Data, Result, Error, ErrorKind, Config, WriteTransaction};
 
 #[test]
 fn test_rollback_update() {
@@ -15,7 +17,7 @@ fn test_rollback_update() {
                                            txn.update("1", Data::Int(2));
                                            Err(Error::new(ErrorKind::DataBaseClosed))
                                        });
-    assert!(update_fail_result.is_ok());
+    assert!(update_fail_result.is_err());
 
     let read_result = db.read(|txn| -> Result<()> {
                                   assert_eq!(&Data::Int(1), txn.get("1").unwrap());
@@ -43,7 +45,7 @@ fn test_rollback_remove() {
                                            txn.remove("1");
                                            Err(Error::new(ErrorKind::DataBaseClosed))
                                        });
-    assert!(update_fail_result.is_ok());
+    assert!(update_fail_result.is_err());
 
     let read_rollback_result = db.read(|txn| -> Result<()> {
                                            assert_eq!(&Data::Int(1), txn.get("1").unwrap());
@@ -67,7 +69,7 @@ fn test_rollback_remove_all() {
                                            txn.clear();
                                            Err(Error::new(ErrorKind::DataBaseClosed))
                                        });
-    assert!(update_fail_result.is_ok());
+    assert!(update_fail_result.is_err());
 
     let read_rollback_result = db.read(|txn| -> Result<()> {
                                            assert_eq!(&Data::Int(1), txn.get("1").unwrap());
@@ -76,3 +78,171 @@ fn test_rollback_remove_all() {
                                        });
     assert!(read_rollback_result.is_ok());
 }
+
+#[derive(Debug)]
+enum AppError {
+    Clementine(Error),
+    InsufficientBalance,
+}
+
+impl From<Error> for AppError {
+    fn from(err: Error) -> AppError {
+        AppError::Clementine(err)
+    }
+}
+
+#[test]
+fn test_rollback_returns_error() {
+    let db = &Database::new(Config::default()).unwrap();
+    let result = db.update(|txn| -> Result<()> {
+                               txn.update("1", Data::Int(1));
+                               Err(Error::new(ErrorKind::ItemNotFound))
+                           });
+    match result {
+        Err(Error { kind: ErrorKind::ItemNotFound }) => {}
+        _ => panic!("unexpected result: {:?}", result),
+    }
+}
+
+#[test]
+fn test_rollback_custom_error() {
+    let mut db = Database::new(Config::default()).unwrap();
+    let result = db.update::<AppError, (), _, _>(|txn| {
+        txn.update("balance", Data::Int(-1));
+        Err(AppError::InsufficientBalance)
+    });
+    match result {
+        Err(AppError::InsufficientBalance) => {}
+        _ => panic!("unexpected result: {:?}", result),
+    }
+
+    let ok_result: result::Result<(), AppError> = db.update(|txn| {
+        txn.update("balance", Data::Int(1));
+        Ok(())
+    });
+    assert!(ok_result.is_ok());
+
+    let read_result = db.read(|txn| -> Result<()> {
+                                  assert_eq!(&Data::Int(1), txn.get("balance").unwrap());
+                                  Ok(())
+                              });
+    assert!(read_result.is_ok());
+
+    db.close().unwrap();
+    match db.update::<AppError, (), _, &str>(|_| Ok(())) {
+        Err(AppError::Clementine(Error { kind: ErrorKind::DataBaseClosed })) => {}
+        result => panic!("unexpected result: {:?}", result),
+    }
+}
+
+#[test]
+fn test_rollback_on_panic() {
+    let db = Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  txn.update("1", Data::Int(1));
+                  Ok(())
+              })
+        .unwrap();
+
+    let result = db.update(|txn| -> Result<()> {
+                               txn.update("1", Data::Int(2));
+                               txn.clear();
+                               panic!("boom");
+                           });
+    match result {
+        Err(Error { kind: ErrorKind::TransactionPanicked }) => {}
+        _ => panic!("unexpected result: {:?}", result),
+    }
+
+    // A panic while a write guard is held poisons the lock, but the
+    // guard rolls back the transaction when it is dropped.
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut txn = db.begin_write().unwrap();
+        txn.update("1", Data::Int(3));
+        panic!("boom");
+    }));
+    assert!(result.is_err());
+
+    db.update(|txn| -> Result<()> {
+                  assert_eq!(&Data::Int(1), txn.get("1").unwrap());
+                  txn.update("2", Data::Int(2));
+                  Ok(())
+              })
+        .unwrap();
+    let read_result = db.read(|txn| -> Result<()> {
+                                  assert_eq!(&Data::Int(2), txn.get("2").unwrap());
+                                  Ok(())
+                              });
+    assert!(read_result.is_ok());
+}
+
+#[test]
+fn test_rollback_to_savepoint() {
+    let db = Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  txn.update("1", Data::Int(1));
+                  let savepoint = txn.savepoint();
+                  txn.update("1", Data::Int(2));
+                  txn.update("2", Data::Int(2));
+                  txn.rollback_to(&savepoint);
+                  assert_eq!(&Data::Int(1), txn.get("1").unwrap());
+                  assert!(txn.get("2").is_none());
+
+                  // The savepoint could be rolled back to again.
+                  txn.remove("1");
+                  txn.rollback_to(&savepoint);
+                  assert_eq!(&Data::Int(1), txn.get("1").unwrap());
+                  txn.release(savepoint);
+                  txn.update("3", Data::Int(3));
+                  Ok(())
+              })
+        .unwrap();
+
+    db.read(|txn| -> Result<()> {
+                assert_eq!(&Data::Int(1), txn.get("1").unwrap());
+                assert!(txn.get("2").is_none());
+                assert_eq!(&Data::Int(3), txn.get("3").unwrap());
+                assert_eq!(2, txn.len());
+                Ok(())
+            })
+        .unwrap();
+}
+
+#[test]
+fn test_nested_savepoints() {
+    let db = Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  txn.update("1", Data::Int(1));
+                  Ok(())
+              })
+        .unwrap();
+
+    let result = db.update(|txn| -> Result<()> {
+                               let outer = txn.savepoint();
+                               txn.update("2", Data::Int(2));
+                               let inner = txn.savepoint();
+                               txn.clear();
+                               txn.update("3", Data::Int(3));
+                               txn.rollback_to(&inner);
+                               assert_eq!(2, txn.len());
+                               assert!(txn.get("3").is_none());
+
+                               txn.clear();
+                               txn.release(inner);
+                               assert!(txn.is_empty());
+                               txn.rollback_to(&outer);
+                               assert_eq!(&Data::Int(1), txn.get("1").unwrap());
+                               assert!(txn.get("2").is_none());
+
+                               txn.update("4", Data::Int(4));
+                               Err(Error::new(ErrorKind::ItemNotFound))
+                           });
+    assert!(result.is_err());
+
+    db.read(|txn| -> Result<()> {
+                assert_eq!(&Data::Int(1), txn.get("1").unwrap());
+                assert_eq!(1, txn.len());
+                Ok(())
+            })
+        .unwrap();
+}
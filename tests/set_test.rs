@@ -0,0 +1,199 @@
+extern crate clementine;
+
+mod common;
+
+use std::collections::BTreeSet;
+use clementine::{Database, Data, Result, Error, ErrorKind, Config};
+use common::*;
+
+fn set(members: &[&str]) -> BTreeSet<String> {
+    members.iter().map(|&member| String::from(member)).collect()
+}
+
+fn scores(members: &[(&str, f64)]) -> Vec<(String, f64)> {
+    members
+        .iter()
+        .map(|&(member, score)| (String::from(member), score))
+        .collect()
+}
+
+fn smembers(db: &Database, key: &'static str) -> BTreeSet<String> {
+    db.read(|txn| txn.smembers(key)).unwrap()
+}
+
+fn zrange(db: &Database, key: &'static str) -> Vec<(String, f64)> {
+    db.read(|txn| txn.zrange_by_score(key, f64::NEG_INFINITY, f64::INFINITY))
+        .unwrap()
+}
+
+#[test]
+fn test_set() {
+    let db = &Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  assert!(txn.sadd("a", "x")?);
+                  assert!(txn.sadd("a", "y")?);
+                  assert!(!txn.sadd("a", "x")?);
+                  assert!(txn.sismember("a", "x")?);
+                  assert!(!txn.sismember("a", "z")?);
+                  assert!(!txn.sismember("b", "x")?);
+                  assert!(txn.srem("a", "y")?);
+                  assert!(!txn.srem("a", "y")?);
+                  assert!(!txn.srem("b", "y")?);
+                  Ok(())
+              })
+        .unwrap();
+    assert_eq!(set(&["x"]), smembers(db, "a"));
+    assert!(smembers(db, "b").is_empty());
+
+    db.update(|txn| -> Result<()> {
+                  assert!(txn.srem("a", "x")?);
+                  assert!(!txn.contains_key("a"));
+                  Ok(())
+              })
+        .unwrap();
+}
+
+#[test]
+fn test_inter_union() {
+    let db = &Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  for member in &["x", "y", "z"] {
+                      txn.sadd("a", member)?;
+                  }
+                  for member in &["y", "z", "w"] {
+                      txn.sadd("b", member)?;
+                  }
+                  Ok(())
+              })
+        .unwrap();
+    db.read(|txn| -> Result<()> {
+                assert_eq!(set(&["y", "z"]), txn.sinter(&["a", "b"])?);
+                assert_eq!(set(&["x", "y", "z"]), txn.sinter(&["a"])?);
+                assert!(txn.sinter(&["a", "c"])?.is_empty());
+                assert!(txn.sinter(&[])?.is_empty());
+                assert_eq!(set(&["w", "x", "y", "z"]), txn.sunion(&["a", "b", "c"])?);
+                assert!(txn.sunion(&[])?.is_empty());
+                Ok(())
+            })
+        .unwrap();
+}
+
+#[test]
+fn test_sorted_set() {
+    let db = &Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  assert_eq!(None, txn.zadd("a", "x", 3.0)?);
+                  assert_eq!(None, txn.zadd("a", "y", 1.0)?);
+                  assert_eq!(None, txn.zadd("a", "z", 2.0)?);
+                  assert_eq!(Some(3.0), txn.zadd("a", "x", 0.5)?);
+                  assert_eq!(Some(0.5), txn.zscore("a", "x")?);
+                  assert_eq!(None, txn.zscore("a", "w")?);
+                  assert_eq!(None, txn.zscore("b", "x")?);
+                  assert_eq!(Some(2), txn.zrank("a", "z")?);
+                  assert_eq!(None, txn.zrank("a", "w")?);
+                  Ok(())
+              })
+        .unwrap();
+    assert_eq!(scores(&[("x", 0.5), ("y", 1.0), ("z", 2.0)]), zrange(db, "a"));
+    db.read(|txn| -> Result<()> {
+                assert_eq!(scores(&[("y", 1.0), ("z", 2.0)]),
+                           txn.zrange_by_score("a", 1.0, 5.0)?);
+                assert!(txn.zrange_by_score("a", 5.0, 1.0)?.is_empty());
+                assert!(txn.zrange_by_score("b", 0.0, 1.0)?.is_empty());
+                Ok(())
+            })
+        .unwrap();
+
+    db.update(|txn| -> Result<()> {
+                  assert_eq!(Some(1.0), txn.zrem("a", "y")?);
+                  assert_eq!(None, txn.zrem("a", "y")?);
+                  assert_eq!(Some((String::from("x"), 0.5)), txn.zpopmin("a")?);
+                  assert_eq!(Some((String::from("z"), 2.0)), txn.zpopmin("a")?);
+                  assert_eq!(None, txn.zpopmin("a")?);
+                  assert!(!txn.contains_key("a"));
+                  Ok(())
+              })
+        .unwrap();
+}
+
+#[test]
+fn test_nan_score() {
+    let db = &Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  txn.zadd("a", "x", 1.0)?;
+                  match txn.zadd("a", "x", f64::NAN) {
+                      Err(Error { kind: ErrorKind::InvalidScore }) => {}
+                      result => panic!("unexpected result: {:?}", result),
+                  }
+                  assert!(txn.zadd("b", "x", f64::NAN).is_err());
+                  assert!(!txn.contains_key("b"));
+                  Ok(())
+              })
+        .unwrap();
+    assert_eq!(scores(&[("x", 1.0)]), zrange(db, "a"));
+}
+
+#[test]
+fn test_wrong_type() {
+    let db = &Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  txn.sadd("a", "x")?;
+                  txn.zadd("b", "x", 1.0)?;
+                  txn.update("c", Data::Int(1));
+                  assert!(is_wrong_type(txn.zadd("a", "x", 1.0)));
+                  assert!(is_wrong_type(txn.zrank("a", "x")));
+                  assert!(is_wrong_type(txn.sadd("b", "x")));
+                  assert!(is_wrong_type(txn.sismember("b", "x")));
+                  assert!(is_wrong_type(txn.sinter(&["a", "b"])));
+                  assert!(is_wrong_type(txn.sunion(&["a", "c"])));
+                  assert!(is_wrong_type(txn.zpopmin("c")));
+                  assert!(is_wrong_type(txn.srem("c", "x")));
+                  Ok(())
+              })
+        .unwrap();
+}
+
+#[test]
+fn test_rollback() {
+    check_rollback(|txn| {
+                       txn.sadd("a", "x")?;
+                       txn.sadd("a", "y")?;
+                       txn.zadd("b", "x", 1.0)?;
+                       txn.zadd("b", "y", 2.0)?;
+                       Ok(())
+                   },
+                   |txn| {
+                       txn.sadd("a", "z")?;
+                       txn.srem("a", "x")?;
+                       txn.srem("a", "y")?;
+                       txn.srem("a", "z")?;
+                       txn.zadd("b", "x", 5.0)?;
+                       txn.zadd("b", "z", 0.0)?;
+                       txn.zpopmin("b")?;
+                       txn.zrem("b", "y")?;
+                       txn.zrem("b", "x")?;
+                       txn.sadd("c", "x")?;
+                       txn.zadd("d", "x", 1.0)?;
+                       Ok(())
+                   });
+}
+
+#[test]
+fn test_reopen() {
+    check_reopen("test_set_reopen.cdb",
+                 |txn| {
+                     txn.sadd("a", "x")?;
+                     txn.sadd("a", "y")?;
+                     txn.srem("a", "x")?;
+                     txn.zadd("b", "x", 1.5)?;
+                     txn.zadd("b", "y", -2.0)?;
+                     txn.zadd("b", "z", 0.0)?;
+                     txn.zpopmin("b")?;
+                     txn.zadd("b", "x", 0.25)?;
+                     Ok(())
+                 },
+                 |db| {
+                     assert_eq!(set(&["y"]), smembers(db, "a"));
+                     assert_eq!(scores(&[("z", 0.0), ("x", 0.25)]), zrange(db, "b"));
+                 });
+}
@@ -0,0 +1,127 @@
+extern crate clementine;
+
+mod common;
+
+use std::sync::Arc;
+use std::thread;
+use clementine::{Database, Data, Config, ReadTransaction, OpenMode, Result};
+use common::*;
+
+fn get_int(txn: &dyn ReadTransaction<&'static str>, key: &'static str) -> i64 {
+    match txn.get(key) {
+        Some(&Data::Int(value)) => value,
+        value => panic!("unexpected value: {:?}", value),
+    }
+}
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn test_send_sync() {
+    assert_send_sync::<Database>();
+}
+
+#[test]
+fn test_concurrent_writers() {
+    let db = Arc::new(Database::new(Config::default()).unwrap());
+    db.update(|txn| -> Result<()> {
+                  txn.update("counter", Data::Int(0));
+                  Ok(())
+              })
+        .unwrap();
+
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            let db = db.clone();
+            thread::spawn(move || for _ in 0..100 {
+                              db.update(|txn| -> Result<()> {
+                                            let counter = get_int(txn, "counter");
+                                            txn.update("counter", Data::Int(counter + 1));
+                                            Ok(())
+                                        })
+                                  .unwrap();
+                          })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(800, db.read(|txn| Ok(get_int(txn, "counter"))).unwrap());
+}
+
+#[test]
+fn test_concurrent_readers_and_writers() {
+    let db = Arc::new(Database::new(Config::default()).unwrap());
+    db.update(|txn| -> Result<()> {
+                  txn.update("a", Data::Int(100));
+                  txn.update("b", Data::Int(0));
+                  Ok(())
+              })
+        .unwrap();
+
+    let writers: Vec<_> = (0..2)
+        .map(|_| {
+            let db = db.clone();
+            thread::spawn(move || for _ in 0..100 {
+                              db.update(|txn| -> Result<()> {
+                                            let (a, b) = (get_int(txn, "a"), get_int(txn, "b"));
+                                            txn.update("a", Data::Int(a - 1));
+                                            txn.update("b", Data::Int(b + 1));
+                                            Ok(())
+                                        })
+                                  .unwrap();
+                          })
+        })
+        .collect();
+    // Every read transaction should see both keys of the same commit.
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let db = db.clone();
+            thread::spawn(move || for _ in 0..200 {
+                              let sum = db.read(|txn| Ok(get_int(txn, "a") + get_int(txn, "b")))
+                                  .unwrap();
+                              assert_eq!(100, sum);
+                          })
+        })
+        .collect();
+    for handle in writers.into_iter().chain(readers) {
+        handle.join().unwrap();
+    }
+
+    let txn = db.begin_read().unwrap();
+    assert_eq!(-100, get_int(&txn, "a"));
+    assert_eq!(200, get_int(&txn, "b"));
+}
+
+#[test]
+fn test_concurrent_writers_persisted() {
+    let path = get_cdb_path("test_concurrent_writers_persisted.cdb");
+    let config = |open_mode| file_config(&path, open_mode);
+    {
+        let db = Arc::new(Database::new(config(OpenMode::Truncate)).unwrap());
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let db = db.clone();
+                thread::spawn(move || for j in 0..25 {
+                                  db.update(|txn| -> Result<()> {
+                                                txn.update(format!("{}-{}", i, j), Data::Int(j));
+                                                Ok(())
+                                            })
+                                      .unwrap();
+                              })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    let db = Database::new(config(OpenMode::OpenExisting)).unwrap();
+    let txn = db.begin_read().unwrap();
+    assert_eq!(100, ReadTransaction::<&str>::len(&txn));
+    assert_eq!(24, get_int(&txn, "3-24"));
+    drop(txn);
+    drop(db);
+    remove_cdb(&path);
+}
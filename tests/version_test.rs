@@ -0,0 +1,183 @@
+extern crate clementine;
+
+mod common;
+
+use clementine::{Database, Data, Result, Config, Expected, Error, ErrorKind, OpenMode};
+use common::*;
+
+fn version(db: &Database, key: &'static str) -> Option<u64> {
+    db.read(|txn| Ok(txn.get_with_version(key).map(|(_, version)| version)))
+        .unwrap()
+}
+
+fn is_conflict<T>(result: Result<T>) -> bool {
+    matches!(result, Err(Error { kind: ErrorKind::Conflict }))
+}
+
+#[test]
+fn test_get_with_version() {
+    let db = &Database::new(Config::default()).unwrap();
+    assert!(version(db, "a").is_none());
+
+    db.update(|txn| -> Result<()> {
+                  txn.update("a", Data::Int(1));
+                  let (_, first) = txn.get_with_version("a").unwrap();
+                  txn.update("a", Data::Int(2));
+                  assert!(txn.get_with_version("a").unwrap().1 > first);
+                  txn.update("b", Data::Int(3));
+                  Ok(())
+              })
+        .unwrap();
+    let a = version(db, "a").unwrap();
+    let b = version(db, "b").unwrap();
+    assert!(b > a);
+
+    db.update(|txn| -> Result<()> {
+                  txn.update("a", Data::Int(2));
+                  Ok(())
+              })
+        .unwrap();
+    assert!(version(db, "a").unwrap() > b);
+    assert_eq!(Some(b), version(db, "b"));
+
+    db.update(|txn| -> Result<()> {
+                  txn.remove("a");
+                  Ok(())
+              })
+        .unwrap();
+    assert!(version(db, "a").is_none());
+}
+
+#[test]
+fn test_compare_and_swap() {
+    let db = &Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  let first = txn.compare_and_swap("a", Expected::Absent, Data::Int(1))?;
+                  assert!(is_conflict(txn.compare_and_swap("a", Expected::Absent, Data::Int(2))));
+                  assert!(is_conflict(txn.compare_and_swap("a",
+                                                           Expected::Version(first + 1),
+                                                           Data::Int(2))));
+                  assert!(is_conflict(txn.compare_and_swap("a",
+                                                           Expected::Value(Data::Int(2)),
+                                                           Data::Int(2))));
+                  assert_eq!(Some((&Data::Int(1), first)), txn.get_with_version("a"));
+
+                  let second = txn.compare_and_swap("a", Expected::Version(first), Data::Int(2))?;
+                  assert!(second > first);
+                  txn.compare_and_swap("a", Expected::Value(Data::Int(2)), Data::Int(3))?;
+                  Ok(())
+              })
+        .unwrap();
+    assert_eq!(Some(Data::Int(3)),
+               db.read(|txn| Ok(txn.get("a").cloned())).unwrap());
+}
+
+#[test]
+fn test_compare_and_remove() {
+    let db = &Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  assert!(is_conflict(txn.compare_and_remove("a", Expected::Absent)));
+                  let version = txn.compare_and_swap("a", Expected::Absent, Data::Int(1))?;
+                  assert!(is_conflict(txn.compare_and_remove("a", Expected::Version(version + 1))));
+                  assert!(is_conflict(txn.compare_and_remove("a", Expected::Value(Data::Int(2)))));
+                  assert_eq!(Data::Int(1),
+                             txn.compare_and_remove("a", Expected::Version(version))?);
+                  assert!(is_conflict(txn.compare_and_remove("a", Expected::Version(version))));
+                  Ok(())
+              })
+        .unwrap();
+    assert!(version(db, "a").is_none());
+}
+
+#[test]
+fn test_optimistic_update() {
+    let db = &Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  txn.update("a", Data::Int(1));
+                  Ok(())
+              })
+        .unwrap();
+    let read = version(db, "a").unwrap();
+
+    // The key is changed by another transaction after it is read.
+    db.update(|txn| -> Result<()> {
+                  txn.update("a", Data::Int(2));
+                  Ok(())
+              })
+        .unwrap();
+
+    let result = db.update(|txn| -> Result<u64> {
+                               txn.compare_and_swap("a", Expected::Version(read), Data::Int(10))
+                           });
+    assert!(is_conflict(result));
+    assert_eq!(Some(Data::Int(2)),
+               db.read(|txn| Ok(txn.get("a").cloned())).unwrap());
+}
+
+#[test]
+fn test_rollback_version() {
+    let db = &Database::new(Config::default()).unwrap();
+    db.update(|txn| -> Result<()> {
+                  txn.update("a", Data::Int(1));
+                  Ok(())
+              })
+        .unwrap();
+    let committed = version(db, "a");
+
+    let result = db.update(|txn| -> Result<()> {
+                               txn.update("a", Data::Int(2));
+                               txn.clear();
+                               txn.update("b", Data::Int(3));
+                               Err(Error::new(ErrorKind::ItemNotFound))
+                           });
+    assert!(result.is_err());
+    assert_eq!(committed, version(db, "a"));
+    assert!(version(db, "b").is_none());
+}
+
+#[test]
+fn test_reopen_version() {
+    let path = get_cdb_path("test_reopen_version.cdb");
+    let config = |open_mode| file_config(&path, open_mode);
+    let (stale, current) = {
+        let db = Database::new(config(OpenMode::Truncate)).unwrap();
+        let mut versions = Vec::new();
+        for i in 0..2 {
+            db.update(|txn| -> Result<()> {
+                          txn.update("a", Data::Int(i));
+                          txn.update("b", Data::Int(i));
+                          Ok(())
+                      })
+                .unwrap();
+            versions.push(version(&db, "a").unwrap());
+        }
+        (versions[0], version(&db, "b").unwrap())
+    };
+
+    // The loaded keys have a version newer than all the versions
+    // handed out before, so only the current one is still expected.
+    let db = Database::new(config(OpenMode::OpenExisting)).unwrap();
+    assert!(version(&db, "a").unwrap() >= current);
+    db.update(|txn| -> Result<()> {
+                  assert!(is_conflict(txn.compare_and_swap("a", Expected::Version(stale),
+                                                           Data::Int(2))));
+                  txn.compare_and_swap("b", Expected::Version(current), Data::Int(2))?;
+                  Ok(())
+              })
+        .unwrap();
+    let current = version(&db, "b").unwrap();
+
+    // The versions are kept once the file is compacted.
+    db.compact().unwrap();
+    drop(db);
+    let db = Database::new(config(OpenMode::OpenExisting)).unwrap();
+    assert!(version(&db, "a").unwrap() >= current);
+    db.update(|txn| -> Result<()> {
+                  txn.compare_and_swap("b", Expected::Version(current), Data::Int(3))?;
+                  Ok(())
+              })
+        .unwrap();
+    assert!(version(&db, "b").unwrap() > current);
+    drop(db);
+    remove_cdb(&path);
+}